@@ -0,0 +1,276 @@
+//! Times edits on large documents: `cargo bench --bench storage`.
+//!
+//! Each edit is timed on the editor's storage and on the one it replaced, a `Vec` of rows
+//! that walk their graphemes on every edit, kept below as `baseline`.
+//!
+//! The editor is a binary crate, so the modules behind `Document` are compiled in here directly.
+//! Not all of their code is used here, and their tests are compiled but not run.
+#![cfg_attr(test, allow(unused_imports))]
+
+#[allow(dead_code)]
+#[path = "../src/atomic_write.rs"]
+mod atomic_write;
+#[allow(dead_code)]
+#[path = "../src/document.rs"]
+mod document;
+#[allow(dead_code)]
+#[path = "../src/encoding.rs"]
+mod encoding;
+#[allow(dead_code)]
+#[path = "../src/filetype.rs"]
+mod filetype;
+#[allow(dead_code)]
+#[path = "../src/highlighting.rs"]
+mod highlighting;
+#[allow(dead_code)]
+#[path = "../src/history.rs"]
+mod history;
+#[allow(dead_code)]
+#[path = "../src/line_index.rs"]
+mod line_index;
+#[allow(dead_code)]
+#[path = "../src/position.rs"]
+mod position;
+#[allow(dead_code)]
+#[path = "../src/rope.rs"]
+mod rope;
+#[allow(dead_code)]
+#[path = "../src/row.rs"]
+mod row;
+#[allow(dead_code)]
+#[path = "../src/search.rs"]
+mod search;
+
+use document::Document;
+use position::Position;
+use search::SearchDirection;
+use std::{
+    env, fs,
+    time::{Duration, Instant},
+};
+
+const ROWS: usize = 200_000;
+const LONG_LINE: usize = 50_000;
+const EDITS: usize = 1_000;
+
+fn main() {
+    let many_rows: String = (0..ROWS)
+        .map(|i| {
+            format!(
+                "{i:>8} INFO request handled in {}ms by worker {}\n",
+                i % 97,
+                i % 13
+            )
+        })
+        .collect();
+    let long_line = format!("{}\n", "let x=[1,2,3];".repeat(LONG_LINE / 14));
+    println!("{:<40} {:>10} {:>10}", "", "baseline", "hecto");
+
+    bench(
+        "open 200k rows",
+        || {
+            baseline::Document::open(&many_rows);
+        },
+        || {
+            open("rows", &many_rows);
+        },
+    );
+    let mut old = baseline::Document::open(&many_rows);
+    let mut document = open("rows", &many_rows);
+    bench(
+        "split 1k rows in the middle",
+        || {
+            for _ in 0..EDITS {
+                old.split(10, ROWS / 2);
+            }
+        },
+        || {
+            for _ in 0..EDITS {
+                document.insert(&Position { x: 10, y: ROWS / 2 }, '\n');
+            }
+        },
+    );
+    bench(
+        "join 1k rows in the middle",
+        || {
+            for _ in 0..EDITS {
+                old.join(ROWS / 2);
+            }
+        },
+        || {
+            for _ in 0..EDITS {
+                let x = document.row(ROWS / 2).map_or(0, row::Row::len);
+                document.delete(&Position { x, y: ROWS / 2 });
+            }
+        },
+    );
+
+    let mut old = baseline::Document::open(&long_line);
+    let mut document = open("line", &long_line);
+    let middle = Position {
+        x: LONG_LINE / 2,
+        y: 0,
+    };
+    bench(
+        "type 1k characters into a 50k line",
+        || {
+            for _ in 0..EDITS {
+                old.rows[0].insert(middle.x, "a");
+            }
+        },
+        || {
+            for _ in 0..EDITS {
+                document.insert(&middle, 'a');
+            }
+        },
+    );
+    bench(
+        "delete 1k characters from a 50k line",
+        || {
+            for _ in 0..EDITS {
+                old.rows[0].delete(middle.x);
+            }
+        },
+        || {
+            for _ in 0..EDITS {
+                document.delete(&middle);
+            }
+        },
+    );
+    bench(
+        "render the end of a 50k line 1k times",
+        || {
+            let row = &old.rows[0];
+            for _ in 0..EDITS {
+                row.render(row.len() - 80, row.len());
+            }
+        },
+        || {
+            let Some(row) = document.row(0) else {
+                return;
+            };
+            let len = row.len();
+            for _ in 0..EDITS {
+                row.render_spans(len - 80..len, 0..80, 8, &[]);
+            }
+        },
+    );
+}
+
+/// Writes the text to a temporary file and opens it.
+fn open(name: &str, text: &str) -> Document {
+    let path = env::temp_dir().join(format!("hecto-bench-{name}.txt"));
+    fs::write(&path, text).expect("failed to write the benchmark file");
+    let document = Document::open(&path.to_string_lossy()).expect("failed to open the file");
+    let _ = fs::remove_file(&path);
+    document
+}
+
+fn bench(name: &str, baseline: impl FnOnce(), hecto: impl FnOnce()) {
+    let start = Instant::now();
+    baseline();
+    let before: Duration = start.elapsed();
+    let start = Instant::now();
+    hecto();
+    let after: Duration = start.elapsed();
+    println!("{name:<40} {before:>10.2?} {after:>10.2?}");
+}
+
+/// The storage before rows were kept in a rope and cached their grapheme boundaries.
+mod baseline {
+    use unicode_segmentation::UnicodeSegmentation;
+
+    pub struct Document {
+        pub rows: Vec<Row>,
+    }
+
+    impl Document {
+        pub fn open(contents: &str) -> Self {
+            Self {
+                rows: contents.lines().map(Row::from).collect(),
+            }
+        }
+
+        pub fn split(&mut self, x: usize, y: usize) {
+            let new_row = self.rows[y].cut(x);
+            self.rows.insert(y + 1, new_row);
+        }
+
+        pub fn join(&mut self, y: usize) {
+            let next_row = self.rows.remove(y + 1);
+            self.rows[y].append(&next_row);
+        }
+    }
+
+    pub struct Row {
+        string: String,
+        /// The length of the string in graphemes.
+        len: usize,
+    }
+
+    impl From<&str> for Row {
+        fn from(slice: &str) -> Self {
+            Self {
+                string: slice.to_string(),
+                len: slice.graphemes(true).count(),
+            }
+        }
+    }
+
+    impl Row {
+        pub fn len(&self) -> usize {
+            self.len
+        }
+
+        pub fn render(&self, start: usize, end: usize) -> String {
+            self.string
+                .graphemes(true)
+                .skip(start)
+                .take(end - start)
+                .collect()
+        }
+
+        pub fn insert(&mut self, at: usize, s: &str) {
+            let mut result = String::new();
+            for (i, grapheme) in self.string.graphemes(true).enumerate() {
+                if i == at {
+                    result.push_str(s);
+                }
+                result.push_str(grapheme);
+            }
+            self.string = result;
+            self.len = self.string.graphemes(true).count();
+        }
+
+        pub fn delete(&mut self, at: usize) {
+            let mut result = String::new();
+            for (i, grapheme) in self.string.graphemes(true).enumerate() {
+                if i != at {
+                    result.push_str(grapheme);
+                }
+            }
+            self.len -= 1;
+            self.string = result;
+        }
+
+        pub fn append(&mut self, new: &Self) {
+            self.string = format!("{}{}", self.string, new.string);
+            self.len += new.len;
+        }
+
+        pub fn cut(&mut self, at: usize) -> Self {
+            let mut row = String::new();
+            let mut splitted = String::new();
+            for (i, grapheme) in self.string.graphemes(true).enumerate() {
+                if i < at {
+                    row.push_str(grapheme);
+                } else {
+                    splitted.push_str(grapheme);
+                }
+            }
+            self.len = at;
+            self.string = row;
+            Self::from(splitted.as_str())
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    os::unix::fs::{self as unix_fs, MetadataExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    process,
+};
+
+/// Writes a file through a temporary file in the same directory and renames it
+/// over the target, so a crash or a full disk never leaves the file half-written.
+///
+/// Symlinks are followed and the file they point to is replaced.
+/// The permissions and ownership of an existing file are kept. Returns a warning
+/// when the ownership could not be kept, which takes privileges for someone else's file.
+///
+/// If the directory is not writable but the file is, and `in_place` allows it,
+/// the file is overwritten directly instead.
+pub fn write_atomically<F>(
+    path: &Path,
+    in_place: bool,
+    write: F,
+) -> Result<Option<String>, io::Error>
+where
+    F: FnOnce(&mut dyn Write) -> Result<(), io::Error>,
+{
+    let target = resolve_target(path)?;
+    let metadata = match fs::metadata(&target) {
+        Ok(metadata) => Some(metadata),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(with_context(&err, "Could not read", &target)),
+    };
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let file_name = target
+        .file_name()
+        .map_or_else(|| "file".into(), |name| name.to_string_lossy());
+    let temp_path = dir.join(format!(".{}.hecto-{}.tmp", file_name, process::id()));
+
+    let (file, warning) = match create_temp_file(&temp_path, metadata.as_ref()) {
+        Ok(created) => created,
+        Err(err)
+            if in_place && metadata.is_some() && err.kind() == io::ErrorKind::PermissionDenied =>
+        {
+            write_in_place(&target, write)?;
+            return Ok(None);
+        }
+        Err(err) => return Err(err),
+    };
+    let result = write_file(file, &temp_path, write).and_then(|()| {
+        fs::rename(&temp_path, &target)
+            .map_err(|err| with_context(&err, "Could not replace", &target))
+    });
+    if let Err(err) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err);
+    }
+    // Persist the rename itself. Not every file system supports syncing a directory.
+    if let Ok(dir) = fs::File::open(&dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(warning)
+}
+
+/// Creates the temporary file with the ownership and permissions of the target before
+/// anything is written to it, so its contents are never more exposed than the target's.
+/// Returns a warning if the ownership could not be kept.
+fn create_temp_file(
+    path: &Path,
+    metadata: Option<&fs::Metadata>,
+) -> Result<(fs::File, Option<String>), io::Error> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    if metadata.is_some() {
+        options.mode(0o600);
+    }
+    let file = options
+        .open(path)
+        .map_err(|err| with_context(&err, "Could not create", path))?;
+    let mut warning = None;
+    if let Some(metadata) = metadata {
+        // Changing the owner may clear the setuid and setgid bits, so it comes first.
+        if let Err(err) = unix_fs::fchown(&file, Some(metadata.uid()), Some(metadata.gid())) {
+            warning = Some(format!("its owner could not be kept: {err}"));
+        }
+        if let Err(err) = file.set_permissions(metadata.permissions()) {
+            let _ = fs::remove_file(path);
+            return Err(with_context(&err, "Could not set permissions of", path));
+        }
+    }
+    Ok((file, warning))
+}
+
+fn write_file<F>(file: fs::File, path: &Path, write: F) -> Result<(), io::Error>
+where
+    F: FnOnce(&mut dyn Write) -> Result<(), io::Error>,
+{
+    let mut writer = BufWriter::new(file);
+    write(&mut writer).map_err(|err| with_context(&err, "Could not write", path))?;
+    let file = writer
+        .into_inner()
+        .map_err(|err| with_context(err.error(), "Could not write", path))?;
+    file.sync_all()
+        .map_err(|err| with_context(&err, "Could not sync", path))
+}
+
+/// Overwrites the file itself, which keeps its ownership and permissions but may
+/// leave it half-written if the write fails.
+fn write_in_place<F>(path: &Path, write: F) -> Result<(), io::Error>
+where
+    F: FnOnce(&mut dyn Write) -> Result<(), io::Error>,
+{
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(path)
+        .map_err(|err| with_context(&err, "Could not open", path))?;
+    write_file(file, path, write)
+}
+
+fn resolve_target(path: &Path) -> Result<PathBuf, io::Error> {
+    match fs::canonicalize(path) {
+        Ok(target) => Ok(target),
+        // Saving a new file, or a dangling symlink that we create the target of.
+        Err(err) if err.kind() == io::ErrorKind::NotFound => match fs::read_link(path) {
+            Ok(link) => Ok(path.parent().map_or(link.clone(), |dir| dir.join(link))),
+            Err(_) => Ok(path.to_path_buf()),
+        },
+        Err(err) => Err(with_context(&err, "Could not resolve", path)),
+    }
+}
+
+fn with_context(err: &io::Error, action: &str, path: &Path) -> io::Error {
+    io::Error::new(err.kind(), format!("{action} {}: {err}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn temp_file_is_never_more_readable_than_the_target() {
+        let dir = std::env::temp_dir().join(format!("hecto-atomic-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("secret");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let warning = write_atomically(&path, true, |file| {
+            let temp = fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .find(|entry| *entry != path)
+                .unwrap();
+            assert_eq!(fs::metadata(temp)?.permissions().mode() & 0o777, 0o600);
+            file.write_all(b"new")
+        })
+        .unwrap();
+
+        assert!(warning.is_none());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,120 @@
+use crate::terminal::Terminal;
+use std::{
+    env, io,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// A destination for copied text outside the editor.
+pub trait ClipboardProvider {
+    fn copy(&mut self, text: &str) -> Result<(), io::Error>;
+}
+
+/// Asks the terminal to set the clipboard with an OSC 52 escape sequence.
+/// This reaches the local clipboard even when the editor runs over SSH.
+pub struct Osc52;
+
+impl ClipboardProvider for Osc52 {
+    fn copy(&mut self, text: &str) -> Result<(), io::Error> {
+        Terminal::set_clipboard(text)
+    }
+}
+
+/// Pipes the text into a clipboard tool such as `xclip` or `pbcopy`.
+pub struct ExternalCommand {
+    program: &'static str,
+    args: &'static [&'static str],
+}
+
+impl ClipboardProvider for ExternalCommand {
+    fn copy(&mut self, text: &str) -> Result<(), io::Error> {
+        let mut child = Command::new(self.program)
+            .args(self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        let status = child.wait()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "{} exited with {status}",
+                self.program
+            )))
+        }
+    }
+}
+
+/// Returns the clipboard backend with the given name, or `None` for "none".
+pub fn provider(name: &str) -> Result<Option<Box<dyn ClipboardProvider>>, String> {
+    let command = |program, args| -> Option<Box<dyn ClipboardProvider>> {
+        Some(Box::new(ExternalCommand { program, args }))
+    };
+    match name {
+        "none" => Ok(None),
+        "osc52" => Ok(Some(Box::new(Osc52))),
+        "xclip" => Ok(command("xclip", &["-selection", "clipboard"])),
+        "xsel" => Ok(command("xsel", &["--clipboard", "--input"])),
+        "wl-copy" => Ok(command("wl-copy", &[])),
+        "pbcopy" => Ok(command("pbcopy", &[])),
+        "auto" => provider(detect()),
+        _ => Err(format!("Unknown clipboard: {name}")),
+    }
+}
+
+/// Picks a local clipboard tool for the running desktop, falling back to OSC 52
+/// for remote sessions and bare terminals.
+fn detect() -> &'static str {
+    if env::var_os("SSH_TTY").is_some() {
+        "osc52"
+    } else if cfg!(target_os = "macos") {
+        "pbcopy"
+    } else if env::var_os("WAYLAND_DISPLAY").is_some() {
+        "wl-copy"
+    } else if env::var_os("DISPLAY").is_some() {
+        "xclip"
+    } else {
+        "osc52"
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Records the copied texts instead of sending them anywhere.
+    /// Clones share the record, so a test can keep one to look at.
+    #[derive(Clone, Default)]
+    pub struct RecordingClipboard {
+        pub copied: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl ClipboardProvider for RecordingClipboard {
+        fn copy(&mut self, text: &str) -> Result<(), io::Error> {
+            self.copied.borrow_mut().push(text.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn provider_maps_names_to_backends() {
+        assert!(provider("none").unwrap().is_none());
+        for name in ["osc52", "xclip", "xsel", "wl-copy", "pbcopy", "auto"] {
+            assert!(provider(name).unwrap().is_some(), "{name}");
+        }
+    }
+
+    #[test]
+    fn provider_rejects_unknown_names() {
+        assert_eq!(
+            provider("clippy").err().as_deref(),
+            Some("Unknown clipboard: clippy")
+        );
+        assert!(provider("").is_err());
+    }
+}
@@ -0,0 +1,286 @@
+use termion::event::Key;
+
+/// An editor action that can be bound to keys.
+#[derive(PartialEq, Clone, Copy)]
+pub enum Command {
+    Quit,
+    Save,
+    Find,
+    Replace,
+    Undo,
+    Redo,
+    ConvertLineEndings,
+    Copy,
+    Cut,
+    Paste,
+    ToggleMark,
+    ClearSelection,
+    Indent,
+    Outdent,
+    DeleteForward,
+    DeleteBackward,
+    CursorUp,
+    CursorDown,
+    CursorLeft,
+    CursorRight,
+    PageUp,
+    PageDown,
+    LineStart,
+    LineEnd,
+    ToggleLineNumbers,
+    ToggleSoftWrap,
+    Palette,
+    NextBuffer,
+    PreviousBuffer,
+    ListBuffers,
+    OpenFile,
+    CloseBuffer,
+    SplitHorizontal,
+    SplitVertical,
+    ClosePane,
+    NextPane,
+}
+
+/// The name and the description of a command, with the keys bound to it by default.
+pub struct CommandInfo {
+    pub command: Command,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub default_keys: &'static [Key],
+}
+
+/// Every command, in the order they are listed to the user.
+pub const COMMANDS: &[CommandInfo] = &[
+    CommandInfo {
+        command: Command::Quit,
+        name: "quit",
+        description: "Quit hecto",
+        default_keys: &[Key::Ctrl('q')],
+    },
+    CommandInfo {
+        command: Command::Save,
+        name: "save",
+        description: "Save the file",
+        default_keys: &[Key::Ctrl('s')],
+    },
+    CommandInfo {
+        command: Command::Find,
+        name: "find",
+        description: "Search the file",
+        default_keys: &[Key::Ctrl('f')],
+    },
+    CommandInfo {
+        command: Command::Replace,
+        name: "replace",
+        description: "Find and replace",
+        default_keys: &[Key::Ctrl('r')],
+    },
+    CommandInfo {
+        command: Command::Undo,
+        name: "undo",
+        description: "Undo the last change",
+        default_keys: &[Key::Ctrl('z')],
+    },
+    CommandInfo {
+        command: Command::Redo,
+        name: "redo",
+        description: "Redo the last undone change",
+        default_keys: &[Key::Ctrl('y')],
+    },
+    CommandInfo {
+        command: Command::ConvertLineEndings,
+        name: "line-endings",
+        description: "Convert line endings or the final newline",
+        default_keys: &[Key::Ctrl('e')],
+    },
+    CommandInfo {
+        command: Command::Copy,
+        name: "copy",
+        description: "Copy the selection or the current row",
+        default_keys: &[Key::Ctrl('c')],
+    },
+    CommandInfo {
+        command: Command::Cut,
+        name: "cut",
+        description: "Cut the selection or the current row",
+        default_keys: &[Key::Ctrl('x')],
+    },
+    CommandInfo {
+        command: Command::Paste,
+        name: "paste",
+        description: "Paste the copied text",
+        default_keys: &[Key::Ctrl('v')],
+    },
+    CommandInfo {
+        command: Command::ToggleMark,
+        name: "mark",
+        description: "Start or stop selecting with cursor motions",
+        default_keys: &[Key::Null],
+    },
+    CommandInfo {
+        command: Command::ClearSelection,
+        name: "clear-selection",
+        description: "Clear the selection",
+        default_keys: &[Key::Esc],
+    },
+    CommandInfo {
+        command: Command::Indent,
+        name: "indent",
+        description: "Indent the selected rows, or insert a tab",
+        default_keys: &[Key::Char('\t')],
+    },
+    CommandInfo {
+        command: Command::Outdent,
+        name: "outdent",
+        description: "Outdent the selected rows or the current row",
+        default_keys: &[Key::BackTab],
+    },
+    CommandInfo {
+        command: Command::DeleteForward,
+        name: "delete-forward",
+        description: "Delete the selection or the character after the cursor",
+        default_keys: &[Key::Delete],
+    },
+    CommandInfo {
+        command: Command::DeleteBackward,
+        name: "delete-backward",
+        description: "Delete the selection or the character before the cursor",
+        default_keys: &[Key::Backspace],
+    },
+    CommandInfo {
+        command: Command::CursorUp,
+        name: "cursor-up",
+        description: "Move the cursor up",
+        default_keys: &[Key::Up],
+    },
+    CommandInfo {
+        command: Command::CursorDown,
+        name: "cursor-down",
+        description: "Move the cursor down",
+        default_keys: &[Key::Down],
+    },
+    CommandInfo {
+        command: Command::CursorLeft,
+        name: "cursor-left",
+        description: "Move the cursor left",
+        default_keys: &[Key::Left],
+    },
+    CommandInfo {
+        command: Command::CursorRight,
+        name: "cursor-right",
+        description: "Move the cursor right",
+        default_keys: &[Key::Right],
+    },
+    CommandInfo {
+        command: Command::PageUp,
+        name: "page-up",
+        description: "Move the cursor up by a screen",
+        default_keys: &[Key::PageUp],
+    },
+    CommandInfo {
+        command: Command::PageDown,
+        name: "page-down",
+        description: "Move the cursor down by a screen",
+        default_keys: &[Key::PageDown],
+    },
+    CommandInfo {
+        command: Command::LineStart,
+        name: "line-start",
+        description: "Move the cursor to the start of the row",
+        default_keys: &[Key::Home],
+    },
+    CommandInfo {
+        command: Command::LineEnd,
+        name: "line-end",
+        description: "Move the cursor to the end of the row",
+        default_keys: &[Key::End],
+    },
+    CommandInfo {
+        command: Command::ToggleLineNumbers,
+        name: "line-numbers",
+        description: "Switch between no, absolute and relative line numbers",
+        default_keys: &[Key::Ctrl('l')],
+    },
+    CommandInfo {
+        command: Command::ToggleSoftWrap,
+        name: "soft-wrap",
+        description: "Turn soft wrap on or off",
+        default_keys: &[Key::Ctrl('w')],
+    },
+    CommandInfo {
+        command: Command::Palette,
+        name: "command-palette",
+        description: "Run a command by name",
+        default_keys: &[Key::Ctrl('p')],
+    },
+    CommandInfo {
+        command: Command::NextBuffer,
+        name: "next-buffer",
+        description: "Switch to the next open file",
+        default_keys: &[Key::Alt('n')],
+    },
+    CommandInfo {
+        command: Command::PreviousBuffer,
+        name: "previous-buffer",
+        description: "Switch to the previous open file",
+        default_keys: &[Key::Alt('p')],
+    },
+    CommandInfo {
+        command: Command::ListBuffers,
+        name: "list-buffers",
+        description: "Choose an open file to switch to",
+        default_keys: &[Key::Alt('b')],
+    },
+    CommandInfo {
+        command: Command::OpenFile,
+        name: "open-file",
+        description: "Open a file, or start a new one",
+        default_keys: &[Key::Ctrl('o')],
+    },
+    CommandInfo {
+        command: Command::CloseBuffer,
+        name: "close-buffer",
+        description: "Close the current file",
+        default_keys: &[Key::Alt('w')],
+    },
+    CommandInfo {
+        command: Command::SplitHorizontal,
+        name: "split-horizontal",
+        description: "Split the pane into two, one above the other",
+        default_keys: &[Key::Alt('s')],
+    },
+    CommandInfo {
+        command: Command::SplitVertical,
+        name: "split-vertical",
+        description: "Split the pane into two side by side",
+        default_keys: &[Key::Alt('v')],
+    },
+    CommandInfo {
+        command: Command::ClosePane,
+        name: "close-pane",
+        description: "Close the current pane, keeping its file open",
+        default_keys: &[Key::Alt('q')],
+    },
+    CommandInfo {
+        command: Command::NextPane,
+        name: "next-pane",
+        description: "Move the focus to the next pane",
+        default_keys: &[Key::Alt('o')],
+    },
+];
+
+impl Command {
+    pub fn from_name(name: &str) -> Option<Self> {
+        COMMANDS
+            .iter()
+            .find(|info| info.name == name)
+            .map(|info| info.command)
+    }
+
+    pub fn name(self) -> &'static str {
+        COMMANDS
+            .iter()
+            .find(|info| info.command == self)
+            .map_or("", |info| info.name)
+    }
+}
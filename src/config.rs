@@ -0,0 +1,290 @@
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use termion::color;
+
+/// The name of the configuration file in the working directory, overriding the user's one.
+const PROJECT_CONFIG: &str = ".hecto.toml";
+
+/// Settings read from `$XDG_CONFIG_HOME/hecto/config.toml` and `.hecto.toml`.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The number of columns between tab stops.
+    pub tab_width: usize,
+    pub line_numbers: LineNumbers,
+    pub soft_wrap: bool,
+    /// How many more times Ctrl-Q must be pressed to quit with unsaved changes.
+    pub quit_times: u8,
+    /// How long a message stays in the message bar, in seconds.
+    pub message_timeout: u64,
+    /// How long to wait after the last input before saving a modified file,
+    /// in seconds. 0 disables autosave.
+    pub autosave: u64,
+    /// The size in megabytes from which files are read as they are shown instead of
+    /// all at once. 0 always reads the whole file.
+    pub large_file: u64,
+    /// The clipboard backend, e.g. "osc52", "xclip" or "none".
+    pub clipboard: String,
+    pub colors: Colors,
+    /// Key sequences bound to commands by name, e.g. `save = "ctrl-x ctrl-s"`,
+    /// which also needs `cut` moved off Ctrl-X. An empty sequence unbinds the command.
+    pub keys: BTreeMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            tab_width: 8,
+            line_numbers: LineNumbers::Off,
+            soft_wrap: false,
+            quit_times: 3,
+            message_timeout: 5,
+            autosave: 0,
+            large_file: 64,
+            clipboard: String::from("osc52"),
+            colors: Colors::default(),
+            keys: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    pub status_fg: Color,
+    pub status_bg: Color,
+    pub gutter_fg: Color,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Self {
+            status_fg: Color(color::Rgb(63, 63, 63)),
+            status_bg: Color(color::Rgb(239, 239, 239)),
+            gutter_fg: Color(color::Rgb(120, 120, 120)),
+        }
+    }
+}
+
+/// A color written as `#rrggbb`.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(try_from = "String")]
+pub struct Color(pub color::Rgb);
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid color `{value}`, expected `#rrggbb`");
+        let hex = value
+            .strip_prefix('#')
+            .filter(|hex| hex.len() == 6)
+            .ok_or_else(invalid)?;
+        let channel = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+        };
+        match (channel(0), channel(2), channel(4)) {
+            (Some(r), Some(g), Some(b)) => Ok(Self(color::Rgb(r, g, b))),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// How line numbers are shown in the gutter.
+#[derive(Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    /// The distance from the cursor row, except for the cursor row itself.
+    Relative,
+}
+
+impl LineNumbers {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Off => Self::Absolute,
+            Self::Absolute => Self::Relative,
+            Self::Relative => Self::Off,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the user's configuration and then the project's one, whose settings take precedence.
+    /// Missing files are skipped. An error names the file and the line at fault.
+    pub fn load() -> Result<Self, String> {
+        Self::load_from(paths())
+    }
+
+    /// Reads the files in order, the settings of later ones taking precedence.
+    fn load_from(paths: Vec<PathBuf>) -> Result<Self, String> {
+        let mut table = toml::Table::new();
+        for path in paths {
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(format!("{}: {err}", path.display())),
+            };
+            // Check each file on its own so that errors point into it.
+            toml::from_str::<Self>(&text).map_err(|err| describe(&path, &text, &err))?;
+            let overrides = text
+                .parse::<toml::Table>()
+                .map_err(|err| describe(&path, &text, &err))?;
+            merge(&mut table, overrides);
+        }
+        let config: Self = toml::Value::Table(table)
+            .try_into()
+            .map_err(|err: toml::de::Error| err.message().to_string())?;
+        if config.tab_width == 0 {
+            return Err(String::from("tab_width must be greater than 0"));
+        }
+        Ok(config)
+    }
+
+    pub fn message_timeout(&self) -> Duration {
+        Duration::from_secs(self.message_timeout)
+    }
+
+    pub fn autosave(&self) -> Option<Duration> {
+        (self.autosave > 0).then(|| Duration::from_secs(self.autosave))
+    }
+
+    /// Returns the size in bytes from which files are opened lazily.
+    pub fn large_file(&self) -> Option<u64> {
+        (self.large_file > 0).then(|| self.large_file.saturating_mul(1024 * 1024))
+    }
+}
+
+fn paths() -> Vec<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+    config_home
+        .map(|dir| dir.join("hecto").join("config.toml"))
+        .into_iter()
+        .chain([PathBuf::from(PROJECT_CONFIG)])
+        .collect()
+}
+
+fn describe(path: &Path, text: &str, err: &toml::de::Error) -> String {
+    let line = err
+        .span()
+        .map(|span| text[..span.start].matches('\n').count() + 1);
+    match line {
+        Some(line) => format!("{}:{line}: {}", path.display(), err.message()),
+        None => format!("{}: {}", path.display(), err.message()),
+    }
+}
+
+/// Copies the settings over the base ones, merging tables key by key.
+fn merge(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(value)) => merge(base, value),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes the files into a new directory and returns their paths.
+    fn write_files(name: &str, files: &[&str]) -> Vec<PathBuf> {
+        let dir = env::temp_dir().join(format!("hecto-config-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        files
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                let path = dir.join(format!("{i}.toml"));
+                fs::write(&path, text).unwrap();
+                path
+            })
+            .collect()
+    }
+
+    fn remove_files(paths: &[PathBuf]) {
+        if let Some(dir) = paths.first().and_then(|path| path.parent()) {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn project_settings_take_precedence() {
+        let user = "tab_width = 2\nsoft_wrap = true\n[colors]\nstatus_fg = \"#010203\"\n[keys]\nsave = \"f2\"\n";
+        let project = "tab_width = 4\n[colors]\ngutter_fg = \"#0a0b0c\"\n[keys]\nquit = \"f10\"\n";
+        let mut paths = write_files("merge", &[user, project]);
+        paths.push(paths[0].with_file_name("missing.toml"));
+
+        let config = Config::load_from(paths.clone()).unwrap();
+        assert_eq!(config.tab_width, 4);
+        assert!(config.soft_wrap);
+        assert!(config.colors.status_fg.0 == color::Rgb(1, 2, 3));
+        assert!(config.colors.gutter_fg.0 == color::Rgb(10, 11, 12));
+        assert!(config.colors.status_bg.0 == Colors::default().status_bg.0);
+        assert_eq!(config.keys.len(), 2);
+        assert_eq!(config.quit_times, 3);
+        remove_files(&paths);
+    }
+
+    #[test]
+    fn errors_name_the_file_and_line() {
+        for (text, line, message) in [
+            ("tab_width = 4\nbogus = 1\n", 2, "unknown field `bogus`"),
+            ("bogus = 1\n", 1, "unknown field `bogus`"),
+            ("\n\ntab_width = \"x\"\n", 3, "invalid type"),
+            (
+                "[colors]\nstatus_fg = \"#12345\"\n",
+                2,
+                "invalid color `#12345`",
+            ),
+            ("tab_width = \n", 1, ""),
+        ] {
+            let paths = write_files("error", &[text]);
+            let err = Config::load_from(paths.clone()).err().unwrap();
+            let prefix = format!("{}:{line}: ", paths[0].display());
+            assert!(err.starts_with(&prefix), "{err}");
+            assert!(err.contains(message), "{err}");
+            remove_files(&paths);
+        }
+    }
+
+    #[test]
+    fn tab_width_must_not_be_zero() {
+        let paths = write_files("tab", &["tab_width = 0\n"]);
+        let err = Config::load_from(paths.clone()).err().unwrap();
+        assert_eq!(err, "tab_width must be greater than 0");
+        remove_files(&paths);
+    }
+
+    #[test]
+    fn colors_are_parsed_from_hex() {
+        let color = Color::try_from(String::from("#ff8000")).ok().unwrap();
+        assert!(color.0 == color::Rgb(255, 128, 0));
+        for value in ["ff8000", "#ff800", "#ff80000", "#gg8000", "#ff80\u{e9}"] {
+            let err = Color::try_from(value.to_string()).err().unwrap();
+            assert_eq!(err, format!("invalid color `{value}`, expected `#rrggbb`"));
+        }
+    }
+
+    #[test]
+    fn merge_replaces_values_and_merges_tables() {
+        let mut base: toml::Table = "a = 1\n[t]\nx = 1\ny = 2\n".parse().unwrap();
+        let overrides: toml::Table = "a = [2]\n[t]\ny = 3\n".parse().unwrap();
+        merge(&mut base, overrides);
+        let expected: toml::Table = "a = [2]\n[t]\nx = 1\ny = 3\n".parse().unwrap();
+        assert_eq!(base, expected);
+    }
+}
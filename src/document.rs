@@ -1,30 +1,218 @@
-use crate::{row::Row, Position, SearchDirection};
+use crate::{
+    atomic_write::write_atomically,
+    encoding::Encoding,
+    filetype::FileType,
+    history::{Edit, History, Offset},
+    line_index::{self, IndexEvent},
+    rope::Rope,
+    row::Row,
+    search::SearchQuery,
+    Position, SearchDirection,
+};
 use std::{
-    fs,
-    io::{self, Write},
+    fs::{self, File},
+    io,
+    ops::Range,
+    os::unix::fs::FileExt,
+    path::Path,
+    sync::mpsc::{Receiver, TryRecvError},
 };
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    /// Detects the line ending mostly used in the text. Defaults to LF.
+    fn detect(text: &str) -> Self {
+        let lf = text.matches('\n').count();
+        let crlf = text.matches("\r\n").count();
+        if crlf > lf - crlf {
+            Self::CrLf
+        } else {
+            Self::Lf
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Lf => "LF",
+            Self::CrLf => "CRLF",
+        }
+    }
+}
 
-#[derive(Default)]
 pub struct Document {
-    rows: Vec<Row>,
+    rows: Rope,
     pub file_name: Option<String>,
-    dirty: bool,
+    file_type: FileType,
+    history: History,
+    line_ending: LineEnding,
+    encoding: Encoding,
+    /// Whether the last row is terminated by a line ending.
+    final_newline: bool,
+    /// The lines of a file opened lazily still being found in the background.
+    indexing: Option<Indexing>,
+}
+
+/// How far the lines of a file opened lazily were found.
+struct Indexing {
+    receiver: Receiver<IndexEvent>,
+    scanned: u64,
+    size: u64,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self {
+            rows: Rope::default(),
+            file_name: None,
+            file_type: FileType::default(),
+            history: History::default(),
+            line_ending: LineEnding::Lf,
+            encoding: Encoding::default(),
+            final_newline: true,
+            indexing: None,
+        }
+    }
 }
 
 impl Document {
+    /// Opens the file, decoding it from the encoding detected. Binary files are refused.
     pub fn open(file_name: &str) -> Result<Self, io::Error> {
-        let contents = fs::read_to_string(file_name)?;
-        let mut rows = Vec::new();
-        for line in contents.lines() {
-            rows.push(Row::from(line));
-        }
+        let bytes = fs::read(file_name)?;
+        let encoding = Encoding::detect(&bytes, true).ok_or_else(binary_file_error)?;
+        let contents = encoding.decode(&bytes[encoding.bom().len()..]);
+        let rows = contents.lines().map(Row::from).collect();
         Ok(Self {
             rows,
             file_name: Some(file_name.to_string()),
-            dirty: false,
+            file_type: FileType::from(file_name),
+            history: History::default(),
+            line_ending: LineEnding::detect(&contents),
+            encoding,
+            // An empty file does not tell, so text typed into it gets a final line break.
+            final_newline: contents.is_empty() || contents.ends_with('\n'),
+            indexing: None,
+        })
+    }
+
+    /// Opens the file without reading it. Its lines are found in the background,
+    /// see `load_lines`, and each row is only read when it is shown or edited.
+    /// The encoding is detected from the start of the file. If invalid UTF-8 is found
+    /// later on, the file is shown as Latin-1 instead, see `load_lines`.
+    /// UTF-16 files are read at once since their line breaks span two bytes.
+    pub fn open_lazy(file_name: &str) -> Result<Self, io::Error> {
+        let file = File::open(file_name)?;
+        let size = file.metadata()?.len();
+        let mut head = vec![0; 64 * 1024];
+        let read = file.read_at(&mut head, 0)?;
+        head.truncate(read);
+        let encoding = Encoding::detect(&head, false).ok_or_else(binary_file_error)?;
+        if matches!(encoding, Encoding::Utf16Le | Encoding::Utf16Be) {
+            return Self::open(file_name);
+        }
+        let start = encoding.bom().len();
+        let line_ending = LineEnding::detect(&encoding.decode(&head[start..]));
+        let check_utf8 = encoding != Encoding::Latin1;
+        let receiver = line_index::spawn(file.try_clone()?, start as u64, check_utf8);
+        Ok(Self {
+            rows: Rope::lazy(file, encoding),
+            file_name: Some(file_name.to_string()),
+            file_type: FileType::from(file_name),
+            history: History::default(),
+            line_ending,
+            encoding,
+            final_newline: true,
+            indexing: Some(Indexing {
+                receiver,
+                scanned: 0,
+                size,
+            }),
         })
     }
 
+    /// Adds the rows found since the last call in a file opened lazily.
+    /// Returns a warning if the file turned out not to be UTF-8 and is now shown as Latin-1.
+    pub fn load_lines(&mut self) -> Result<Option<String>, io::Error> {
+        let encoding = self.encoding;
+        self.receive_lines(false)?;
+        Ok((self.encoding != encoding).then(|| {
+            let name = self.file_name.as_deref().unwrap_or_default();
+            format!(
+                "{name} is not valid {}, showing it as Latin-1",
+                encoding.name()
+            )
+        }))
+    }
+
+    /// Returns how much of a file opened lazily was searched for lines, in percent,
+    /// or `None` once all the rows are known.
+    pub fn indexing_progress(&self) -> Option<u64> {
+        let indexing = self.indexing.as_ref()?;
+        Some(indexing.scanned * 100 / indexing.size.max(1))
+    }
+
+    /// Finds the rest of the lines before a row is added after the last one found,
+    /// which would otherwise end up in the middle of the file.
+    fn wait_for_end(&mut self, at: &Position) {
+        if self.indexing.is_some() && at.y >= self.len() {
+            // A read error leaves the rows found so far, as when it is reported by `load_lines`.
+            let _ = self.receive_lines(true);
+        }
+    }
+
+    fn receive_lines(&mut self, wait: bool) -> Result<(), io::Error> {
+        while let Some(indexing) = &mut self.indexing {
+            let event = if wait {
+                indexing.receiver.recv().ok()
+            } else {
+                match indexing.receiver.try_recv() {
+                    Ok(event) => Some(event),
+                    Err(TryRecvError::Empty) => return Ok(()),
+                    Err(TryRecvError::Disconnected) => None,
+                }
+            };
+            match event {
+                Some(IndexEvent::Lines { lines, scanned }) => {
+                    indexing.scanned = scanned;
+                    self.rows.extend_unloaded(lines);
+                }
+                Some(IndexEvent::NotUtf8) => {
+                    // Rows edited so far keep their text, and fail to save if it has
+                    // replacement characters, as Latin-1 cannot encode them.
+                    self.rows
+                        .set_encoding(Encoding::Latin1, self.encoding.bom().len());
+                    self.encoding = Encoding::Latin1;
+                }
+                Some(IndexEvent::Done { final_newline }) => {
+                    self.final_newline = final_newline;
+                    self.indexing = None;
+                }
+                Some(IndexEvent::Failed(err)) => {
+                    self.indexing = None;
+                    return Err(err);
+                }
+                None => self.indexing = None,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn file_type(&self) -> String {
+        self.file_type.name()
+    }
+
     pub fn row(&self, index: usize) -> Option<&Row> {
         self.rows.get(index)
     }
@@ -38,95 +226,642 @@ impl Document {
     }
 
     pub fn insert(&mut self, at: &Position, c: char) {
+        self.wait_for_end(at);
         if at.y > self.len() {
             return;
         }
-        self.dirty = true;
+        if at.y == self.len() {
+            self.perform(Edit::PushRow { at: at.clone() });
+            if c == '\n' {
+                return;
+            }
+        }
+        let at = at.clone();
         if c == '\n' {
-            self.insert_newline(at);
+            self.perform(Edit::Split { at });
+        } else {
+            let byte = self.byte_index(&at);
+            self.perform(Edit::Insert {
+                at,
+                byte,
+                text: c.to_string(),
+            });
+        }
+    }
+
+    pub fn delete(&mut self, at: &Position) {
+        // Deleting at the end of the last row found so far joins the next one, if any.
+        self.wait_for_end(&Position {
+            x: 0,
+            y: at.y.saturating_add(1),
+        });
+        let Some(row) = self.rows.get(at.y) else {
             return;
+        };
+        if at.x < row.len() {
+            let text = row.render(at.x, at.x.saturating_add(1));
+            let byte = row.byte_index(at.x);
+            self.perform(Edit::Delete {
+                at: at.clone(),
+                byte,
+                text,
+            });
+        } else if at.y < self.len() - 1 {
+            let x = row.len();
+            self.perform(Edit::Join {
+                at: Position { x, y: at.y },
+            });
         }
-        if at.y == self.len() {
-            let mut row = Row::default();
-            row.insert(0, c);
-            self.rows.push(row);
-        } else if let Some(row) = self.rows.get_mut(at.y) {
-            row.insert(at.x, c);
+    }
+
+    /// Replaces `len` graphemes at the position with the text, as a single undoable step.
+    pub fn replace(&mut self, at: &Position, len: usize, text: &str) {
+        if at.y >= self.len() {
+            return;
+        }
+        let end = Position {
+            x: at.x.saturating_add(len),
+            y: at.y,
+        };
+        self.history.begin_group();
+        self.delete_range(at, &end);
+        self.insert_text(at, text);
+        self.history.end_group();
+    }
+
+    /// Returns the text between the positions, with rows joined by `\n`.
+    pub fn text(&self, start: &Position, end: &Position) -> String {
+        let end = self.clamp(end);
+        let mut text = String::new();
+        for y in start.y..=end.y {
+            let Some(row) = self.rows.get(y) else {
+                break;
+            };
+            let from = if y == start.y { start.x } else { 0 };
+            let to = if y == end.y { end.x } else { row.len() };
+            text.push_str(&row.render(from, to));
+            if y < end.y {
+                text.push('\n');
+            }
         }
+        text
     }
 
-    fn insert_newline(&mut self, at: &Position) {
+    /// Inserts the text, which may span multiple rows, as a single undoable step
+    /// and returns the position just after it.
+    pub fn insert_text(&mut self, at: &Position, text: &str) -> Position {
+        self.wait_for_end(at);
+        if at.y > self.len() || text.is_empty() {
+            return at.clone();
+        }
+        let text = text.replace("\r\n", "\n");
+        self.history.begin_group();
         if at.y == self.len() {
-            self.rows.push(Row::default());
-            return;
+            self.perform(Edit::PushRow { at: at.clone() });
         }
-        let new_row = self.rows.get_mut(at.y).unwrap().cut(at.x);
-        self.rows.insert(at.y + 1, new_row);
+        let at = self.clamp(at);
+        let start = self.offset(&at);
+        let (end, after) = self.insert_rows(start, &text);
+        self.history.record(Edit::InsertText {
+            at,
+            start,
+            end,
+            text,
+        });
+        self.history.end_group();
+        after
     }
 
-    pub fn delete(&mut self, at: &Position) {
-        if at.y >= self.len() {
+    /// Replaces the text between the positions as a single undoable step
+    /// and returns the position just after the new text.
+    pub fn replace_range(&mut self, start: &Position, end: &Position, text: &str) -> Position {
+        self.history.begin_group();
+        self.delete_range(start, end);
+        let end = self.insert_text(start, text);
+        self.history.end_group();
+        end
+    }
+
+    /// Deletes the text between the positions as a single undoable step.
+    pub fn delete_range(&mut self, start: &Position, end: &Position) {
+        let start = self.clamp(start);
+        let end = self.clamp(end);
+        if (start.y, start.x) >= (end.y, end.x) {
             return;
         }
-        self.dirty = true;
-        if at.x == self.rows.get(at.y).unwrap().len() && at.y < self.len() - 1 {
-            let next_row = self.rows.remove(at.y + 1);
-            let row = self.rows.get_mut(at.y).unwrap();
-            row.append(&next_row);
-        } else {
-            let row = self.rows.get_mut(at.y).unwrap();
-            row.delete(at.x);
+        let text = self.text(&start, &end);
+        self.perform(Edit::DeleteText {
+            start: self.offset(&start),
+            end: self.offset(&end),
+            at: start,
+            text,
+        });
+    }
+
+    /// Inserts the indent at the start of every non-empty row in the range.
+    pub fn indent(&mut self, rows: Range<usize>, indent: &str) {
+        self.history.begin_group();
+        for y in rows {
+            if self.rows.get(y).is_none_or(|row| row.len() == 0) {
+                continue;
+            }
+            let mut byte = 0;
+            for (x, grapheme) in indent.graphemes(true).enumerate() {
+                self.perform(Edit::Insert {
+                    at: Position { x, y },
+                    byte,
+                    text: grapheme.to_string(),
+                });
+                byte += grapheme.len();
+            }
         }
+        self.history.end_group();
     }
 
-    pub fn save(&mut self) -> Result<(), io::Error> {
-        if let Some(file_name) = &self.file_name {
-            let mut file = fs::File::create(file_name)?;
-            for row in &self.rows {
-                file.write_all(row.as_bytes())?;
-                file.write_all(b"\n")?;
+    /// Removes a leading tab, or up to `width` leading spaces, from every row in the range.
+    pub fn outdent(&mut self, rows: Range<usize>, width: usize) {
+        self.history.begin_group();
+        for y in rows {
+            let Some(row) = self.rows.get(y) else {
+                break;
+            };
+            let prefix = row.render(0, width);
+            let count = if prefix.starts_with('\t') {
+                1
+            } else {
+                prefix.chars().take_while(|c| *c == ' ').count()
+            };
+            for _ in 0..count {
+                self.delete(&Position { x: 0, y });
             }
-            self.dirty = false;
         }
-        Ok(())
+        self.history.end_group();
+    }
+
+    /// Returns the byte index in its row where the grapheme at the position starts.
+    fn byte_index(&self, at: &Position) -> usize {
+        self.rows.get(at.y).map_or(0, |row| row.byte_index(at.x))
+    }
+
+    fn offset(&self, at: &Position) -> Offset {
+        Offset {
+            y: at.y,
+            byte: self.byte_index(at),
+        }
+    }
+
+    /// Moves the position into the document, onto the end of the last row at most.
+    fn clamp(&self, at: &Position) -> Position {
+        match self.rows.get(at.y) {
+            Some(row) => Position {
+                x: at.x.min(row.len()),
+                y: at.y,
+            },
+            None => Position {
+                x: self.rows.last().map_or(0, Row::len),
+                y: self.len().saturating_sub(1),
+            },
+        }
+    }
+
+    /// Reverts the last group of edits and returns the position where it happened.
+    pub fn undo(&mut self) -> Option<Position> {
+        let edits = self.history.undo()?;
+        edits.into_iter().map(|edit| self.revert(&edit)).last()
+    }
+
+    /// Re-applies the last undone group of edits and returns the position after it.
+    pub fn redo(&mut self) -> Option<Position> {
+        let edits = self.history.redo()?;
+        edits.into_iter().map(|edit| self.apply(&edit)).last()
+    }
+
+    fn perform(&mut self, edit: Edit) {
+        self.apply(&edit);
+        self.history.record(edit);
+    }
+
+    /// Applies the edit and returns the position just after the change.
+    fn apply(&mut self, edit: &Edit) -> Position {
+        match edit {
+            Edit::Insert { at, byte, text } => {
+                let Some(row) = self.rows.get_mut(at.y) else {
+                    return at.clone();
+                };
+                row.insert_str(*byte, text);
+                Position {
+                    x: row.grapheme_end(byte + text.len()),
+                    y: at.y,
+                }
+            }
+            Edit::Delete { at, byte, text } => {
+                if let Some(row) = self.rows.get_mut(at.y) {
+                    row.remove_str(*byte..byte + text.len());
+                }
+                at.clone()
+            }
+            Edit::Split { at } => {
+                if let Some(row) = self.rows.get_mut(at.y) {
+                    let new_row = row.cut(at.x);
+                    self.rows.insert(at.y + 1, new_row);
+                }
+                Position {
+                    x: 0,
+                    y: at.y.saturating_add(1),
+                }
+            }
+            Edit::Join { at } => {
+                if at.y + 1 < self.len() {
+                    let next_row = self.rows.remove(at.y + 1);
+                    if let Some(row) = self.rows.get_mut(at.y) {
+                        row.append(&next_row);
+                    }
+                }
+                at.clone()
+            }
+            Edit::PushRow { at } => {
+                self.rows.push(Row::default());
+                Position { x: 0, y: at.y }
+            }
+            Edit::InsertText { start, text, .. } => self.insert_rows(*start, text).1,
+            Edit::DeleteText { at, start, end, .. } => {
+                self.remove_rows(*start, *end);
+                at.clone()
+            }
+        }
+    }
+
+    /// Reverts the edit and returns the position where the change was.
+    fn revert(&mut self, edit: &Edit) -> Position {
+        match edit {
+            Edit::Insert { at, byte, text } => {
+                if let Some(row) = self.rows.get_mut(at.y) {
+                    row.remove_str(*byte..byte + text.len());
+                }
+                at.clone()
+            }
+            Edit::Delete { at, byte, text } => {
+                if let Some(row) = self.rows.get_mut(at.y) {
+                    row.insert_str(*byte, text);
+                }
+                at.clone()
+            }
+            Edit::Split { at } => {
+                if at.y + 1 < self.len() {
+                    let next_row = self.rows.remove(at.y + 1);
+                    if let Some(row) = self.rows.get_mut(at.y) {
+                        row.append(&next_row);
+                    }
+                }
+                at.clone()
+            }
+            Edit::Join { at } => {
+                if let Some(row) = self.rows.get_mut(at.y) {
+                    let new_row = row.cut(at.x);
+                    self.rows.insert(at.y + 1, new_row);
+                }
+                at.clone()
+            }
+            Edit::PushRow { at } => {
+                self.rows.pop();
+                at.clone()
+            }
+            Edit::InsertText { at, start, end, .. } => {
+                self.remove_rows(*start, *end);
+                at.clone()
+            }
+            Edit::DeleteText {
+                at, start, text, ..
+            } => {
+                self.insert_rows(*start, text);
+                at.clone()
+            }
+        }
+    }
+
+    /// Splices the text into the rows at once. Returns the byte offset just after it,
+    /// and the position of the grapheme after it once combined with the rest of the row.
+    fn insert_rows(&mut self, start: Offset, text: &str) -> (Offset, Position) {
+        let Some(row) = self.rows.get_mut(start.y) else {
+            return (start, Position { x: 0, y: start.y });
+        };
+        let mut lines = text.split('\n');
+        let tail = row.split_off(start.byte);
+        row.insert_str(start.byte, lines.next().unwrap_or_default());
+        let mut new_rows: Vec<Row> = lines.map(Row::from).collect();
+        let y = start.y + new_rows.len();
+        let last_row = new_rows.last_mut().unwrap_or(row);
+        let byte = last_row.as_str().len();
+        last_row.append(&tail);
+        let x = last_row.grapheme_end(byte);
+        self.rows.splice(start.y + 1, new_rows);
+        (Offset { y, byte }, Position { x, y })
+    }
+
+    /// Removes the text between the byte offsets from the rows at once.
+    fn remove_rows(&mut self, start: Offset, end: Offset) {
+        let Some(tail) = self.rows.get_mut(end.y).map(|row| row.split_off(end.byte)) else {
+            return;
+        };
+        self.rows.drain(start.y + 1..end.y + 1);
+        if let Some(row) = self.rows.get_mut(start.y) {
+            row.split_off(start.byte);
+            row.append(&tail);
+        }
+    }
+
+    /// Saves the document to its file and returns a warning about the file, if any.
+    pub fn save(&mut self) -> Result<Option<String>, io::Error> {
+        // Rows not found yet would be missing from the file.
+        self.receive_lines(true)?;
+        let mut warning = None;
+        if let Some(file_name) = &self.file_name {
+            self.file_type = FileType::from(file_name);
+            // Unloaded lines are read from the file, so it cannot be overwritten while saving.
+            let in_place = !self.rows.is_lazy();
+            warning = write_atomically(Path::new(file_name), in_place, |file| {
+                let encoding = self.encoding;
+                let line_ending = encoding.encode(self.line_ending.as_str())?;
+                file.write_all(encoding.bom())?;
+                for i in 0..self.rows.len() {
+                    if i > 0 {
+                        file.write_all(&line_ending)?;
+                    }
+                    let bytes = match self.rows.unchanged_bytes(i) {
+                        Some(bytes) => bytes,
+                        None => encoding.encode(self.rows.peek(i).unwrap_or_default().as_str())?,
+                    };
+                    file.write_all(&bytes)?;
+                }
+                if self.final_newline && !self.rows.is_empty() {
+                    file.write_all(&line_ending)?;
+                }
+                Ok(())
+            })?;
+            self.history.mark_saved();
+        }
+        Ok(warning)
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        if self.line_ending != line_ending {
+            self.line_ending = line_ending;
+            self.history.mark_unsaved();
+        }
+    }
+
+    pub fn has_final_newline(&self) -> bool {
+        self.final_newline
+    }
+
+    pub fn set_final_newline(&mut self, final_newline: bool) {
+        if self.final_newline != final_newline {
+            self.final_newline = final_newline;
+            self.history.mark_unsaved();
+        }
     }
 
     pub fn is_dirty(&self) -> bool {
-        self.dirty
+        self.history.is_modified()
+    }
+
+    /// Highlights the rows in the range, carrying open blocks over from the rows above.
+    /// In a file opened lazily, the rows above are not read to find open blocks.
+    pub fn highlight(&mut self, rows: Range<usize>) {
+        let end = rows.end.min(self.rows.len());
+        let start = if self.rows.is_lazy() {
+            rows.start.min(end)
+        } else {
+            0
+        };
+        let opts = self.file_type.highlighting_options();
+        let mut open_block = None;
+        for y in start..end {
+            if let Some(row) = self.rows.get_shown_mut(y) {
+                open_block = row.highlight(opts, open_block);
+            }
+        }
     }
 
-    pub fn find(&self, query: &str, at: &Position, direction: SearchDirection) -> Option<Position> {
+    /// Returns the position of the next match and its length in graphemes.
+    pub fn find(
+        &self,
+        query: &SearchQuery,
+        at: &Position,
+        direction: SearchDirection,
+    ) -> Option<(Position, usize)> {
         if at.y >= self.rows.len() {
             return None;
         }
+        // Rows of a file opened lazily are read without keeping them.
+        let rows = |range: Range<usize>| range.filter_map(|y| Some((y, self.rows.peek(y)?)));
         match direction {
             SearchDirection::Forward => {
                 let mut x_start = at.x;
-                let rows_to_end = self.rows.iter().enumerate().skip(at.y);
-                for (y, row) in rows_to_end {
-                    if let Some(x) = row.find(query, x_start, direction) {
-                        return Some(Position { x, y });
+                for (y, row) in rows(at.y..self.rows.len()) {
+                    if let Some(range) = row.find(query, x_start, direction) {
+                        return Some((Position { x: range.start, y }, range.len()));
                     }
                     x_start = 0;
                 }
             }
             SearchDirection::Backward => {
                 let mut x_start = at.x;
-                let rows_to_start = self
-                    .rows
-                    .iter()
-                    .enumerate()
-                    .take(at.y.saturating_add(1))
-                    .rev();
-                for (y, row) in rows_to_start {
-                    if let Some(x) = row.find(query, x_start, direction) {
-                        return Some(Position { x, y });
+                for (y, row) in rows(0..at.y + 1).rev() {
+                    if let Some(range) = row.find(query, x_start, direction) {
+                        return Some((Position { x: range.start, y }, range.len()));
                     }
-                    let prev_row = self.rows.get(y.saturating_sub(1));
-                    x_start = prev_row.map_or(0, Row::len);
+                    let prev_row = self.rows.peek(y.saturating_sub(1));
+                    x_start = prev_row.map_or(0, |row| row.len());
                 }
             }
         }
         None
     }
+
+    /// Counts the matches in the whole document, returning the number of matches
+    /// starting before the position and the total. Returns `None` for a file opened
+    /// lazily, which would have to be read entirely on every change of the query.
+    pub fn count_matches(&self, query: &SearchQuery, at: &Position) -> Option<(usize, usize)> {
+        if self.rows.is_lazy() {
+            return None;
+        }
+        let mut before = 0;
+        let mut total = 0;
+        for y in 0..self.rows.len() {
+            let Some(row) = self.rows.get(y) else {
+                break;
+            };
+            for range in row.find_all(query) {
+                if y < at.y || (y == at.y && range.start < at.x) {
+                    before += 1;
+                }
+                total += 1;
+            }
+        }
+        Some((before, total))
+    }
+}
+
+fn binary_file_error() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "binary files cannot be edited")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(text: &str) -> Document {
+        Document {
+            rows: text.split('\n').map(Row::from).collect(),
+            ..Document::default()
+        }
+    }
+
+    fn contents(document: &Document) -> String {
+        (0..document.len())
+            .map(|y| document.row(y).map_or("", Row::as_str))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn undo_insert_combining_with_previous_grapheme() {
+        let mut document = document("ab");
+        document.insert(&Position { x: 1, y: 0 }, '\u{301}');
+        assert_eq!(contents(&document), "a\u{301}b");
+        assert_eq!(document.row(0).map(Row::len), Some(2));
+        document.undo();
+        assert_eq!(contents(&document), "ab");
+        document.redo();
+        assert_eq!(contents(&document), "a\u{301}b");
+    }
+
+    #[test]
+    fn undo_paste_combining_with_previous_grapheme() {
+        let mut document = document("ab");
+        let end = document.insert_text(&Position { x: 1, y: 0 }, "\u{301}");
+        assert_eq!(contents(&document), "a\u{301}b");
+        assert_eq!((end.x, end.y), (1, 0));
+        document.undo();
+        assert_eq!(contents(&document), "ab");
+        document.redo();
+        assert_eq!(contents(&document), "a\u{301}b");
+    }
+
+    #[test]
+    fn undo_multiline_paste_and_delete() {
+        let mut document = document("one\ntwo");
+        document.insert_text(&Position { x: 2, y: 0 }, "x\ny\u{301}\nz");
+        assert_eq!(contents(&document), "onx\ny\u{301}\nze\ntwo");
+        document.delete_range(&Position { x: 1, y: 0 }, &Position { x: 1, y: 3 });
+        assert_eq!(contents(&document), "owo");
+        document.undo();
+        assert_eq!(contents(&document), "onx\ny\u{301}\nze\ntwo");
+        document.undo();
+        assert_eq!(contents(&document), "one\ntwo");
+    }
+
+    #[test]
+    fn dirty_after_save_undo_and_redo() {
+        let path = std::env::temp_dir().join(format!("hecto-dirty-{}", std::process::id()));
+        fs::write(&path, "text\n").unwrap();
+        let mut document = Document::open(&path.to_string_lossy()).unwrap();
+        assert!(!document.is_dirty());
+        document.insert(&Position { x: 0, y: 0 }, 'a');
+        assert!(document.is_dirty());
+        document.save().unwrap();
+        assert!(!document.is_dirty());
+
+        document.undo();
+        assert_eq!(contents(&document), "text");
+        assert!(document.is_dirty());
+        document.redo();
+        assert_eq!(contents(&document), "atext");
+        assert!(!document.is_dirty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn files_are_saved_with_their_line_endings() {
+        let path = std::env::temp_dir().join(format!("hecto-save-{}", std::process::id()));
+        let file_name = path.to_string_lossy().into_owned();
+        for (before, after) in [
+            ("one\r\ntwo\r\n", "aone\r\ntwo\r\n"),
+            ("one\r\ntwo", "aone\r\ntwo"),
+            ("one\ntwo", "aone\ntwo"),
+            ("", "a\n"),
+        ] {
+            for open in [Document::open, Document::open_lazy] {
+                fs::write(&path, before).unwrap();
+                let mut document = open(&file_name).unwrap();
+                document.save().unwrap();
+                assert_eq!(fs::read_to_string(&path).unwrap(), before);
+
+                document.insert(&Position { x: 0, y: 0 }, 'a');
+                document.save().unwrap();
+                assert_eq!(fs::read_to_string(&path).unwrap(), after);
+                let document = Document::open(&file_name).unwrap();
+                assert_eq!(contents(&document), after.trim_end().replace('\r', ""));
+            }
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn latin1_file_without_final_newline_is_saved_unchanged() {
+        let path = std::env::temp_dir().join(format!("hecto-latin1-{}", std::process::id()));
+        fs::write(&path, b"caf\xe9").unwrap();
+        let file_name = path.to_string_lossy().into_owned();
+        let mut document = Document::open(&file_name).unwrap();
+        assert!(document.encoding() == Encoding::Latin1);
+        assert_eq!(contents(&document), "caf\u{e9}");
+        document.save().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"caf\xe9");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn delete_at_the_end_of_the_rows_found_waits_for_the_rest() {
+        let path = std::env::temp_dir().join(format!("hecto-delete-{}", std::process::id()));
+        fs::write(&path, "ab\ncd\n").unwrap();
+        // No rows were received yet right after opening.
+        let mut document = Document::open_lazy(&path.to_string_lossy()).unwrap();
+        document.delete(&Position { x: 2, y: 0 });
+        assert_eq!(contents(&document), "abcd");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn large_file_with_invalid_utf8_later_on_falls_back_to_latin1() {
+        for (bom, first_row) in [
+            ("", "\u{c3}\u{a9}"),
+            ("\u{feff}", "\u{ef}\u{bb}\u{bf}\u{c3}\u{a9}"),
+        ] {
+            let path = std::env::temp_dir().join(format!("hecto-lazy-{}", std::process::id()));
+            let mut bytes = format!("{bom}\u{e9}\n").into_bytes();
+            bytes.extend_from_slice(b"caf\xe9\n");
+            fs::write(&path, &bytes).unwrap();
+            let file_name = path.to_string_lossy().into_owned();
+
+            let mut document = Document::open_lazy(&file_name).unwrap();
+            document.receive_lines(true).unwrap();
+            assert!(document.encoding() == Encoding::Latin1);
+            assert_eq!(contents(&document), format!("{first_row}\ncaf\u{e9}"));
+
+            document.insert(&Position { x: 0, y: 1 }, 'X');
+            document.save().unwrap();
+            bytes.splice(bytes.len() - 5..bytes.len() - 5, *b"X");
+            assert_eq!(fs::read(&path).unwrap(), bytes);
+            fs::remove_file(&path).unwrap();
+        }
+    }
 }
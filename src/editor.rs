@@ -1,57 +1,154 @@
-use crate::{document::Document, row::Row, terminal::Terminal, Position, SearchDirection};
+use crate::{
+    clipboard::{self, ClipboardProvider},
+    command::{Command, COMMANDS},
+    config::{Config, LineNumbers},
+    document::{Document, LineEnding},
+    fuzzy,
+    highlighting::Overlay,
+    keymap::{self, Keymap, Lookup},
+    layout::{Area, Direction, Layout},
+    row::Row,
+    search::{SearchOptions, SearchQuery},
+    terminal::{Event, Terminal},
+    Position, SearchDirection,
+};
 use std::{
-    env, io,
+    cmp::Ordering,
+    env, fs, io, iter, mem,
+    ops::Range,
     time::{Duration, Instant},
 };
-use termion::{color, event::Key};
+use termion::event::Key;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
-const STATUS_FG_COLOR: color::Rgb = color::Rgb(63, 63, 63);
-const STATUS_BG_COLOR: color::Rgb = color::Rgb(239, 239, 239);
-const QUIT_TIMES: u8 = 3;
+const INDENT: &str = "    ";
+/// How often the screen is redrawn while the lines of a large file are found.
+const INDEXING_REFRESH: Duration = Duration::from_millis(100);
 
 pub struct Editor {
     should_quit: bool,
     terminal: Terminal,
     cursor_position: Position,
     offset: Position,
+    /// With soft wrap, the first visual line shown of the row at `offset.y`.
+    offset_line: usize,
     document: Document,
+    /// Every open file. The slot of the current one is left empty while its
+    /// state lives in the fields above.
+    buffers: Vec<Buffer>,
+    /// The index of the current file in `buffers`.
+    current: usize,
+    /// Every pane on the screen. The view of the focused one lives in the fields above.
+    panes: Vec<Pane>,
+    /// The index of the focused pane in `panes`.
+    pane: usize,
+    layout: Layout,
     status_message: StatusMessage,
     quit_times: u8,
+    /// The command `quit_times` counts down for, quitting or closing a file.
+    discard_command: Option<Command>,
+    search_options: SearchOptions,
+    search_highlight: Option<SearchHighlight>,
+    selection: Option<Selection>,
+    picker: Option<Picker>,
+    /// The text copied or cut last, shared by all documents.
+    register: String,
+    /// Where copied text is also sent so that it reaches other applications.
+    clipboard: Option<Box<dyn ClipboardProvider>>,
+    last_input: Instant,
+    config: Config,
+    keymap: Keymap,
+    /// The keys pressed so far of a sequence like `Ctrl-X Ctrl-S`.
+    pending_keys: Vec<Key>,
+    line_numbers: LineNumbers,
+    /// Whether long rows are split across screen lines instead of scrolling horizontally.
+    soft_wrap: bool,
 }
 
 impl Editor {
     pub fn default() -> Self {
         let terminal = Terminal::default().expect("failed to initialize Terminal");
-        let mut initial_status =
-            String::from("HELP: Ctrl-F = find | Ctrl-S = save | Ctrl-Q = quit");
-
-        let args: Vec<String> = env::args().collect();
-        let document = if args.len() > 1 {
-            let file_name = &args[1];
-            if let Ok(doc) = Document::open(file_name) {
-                doc
-            } else {
-                initial_status = format!("ERR: Could not open file: {file_name}");
-                Document::default()
+        let mut initial_status = String::new();
+
+        let config = Config::load().unwrap_or_else(|err| {
+            initial_status = format!("ERR: {err}");
+            Config::default()
+        });
+
+        let mut buffers = Vec::new();
+        for file_name in env::args().skip(1) {
+            match open_document(&file_name, &config) {
+                Ok(document) => buffers.push(Buffer {
+                    document,
+                    ..Buffer::default()
+                }),
+                Err(err) => {
+                    initial_status = format!("ERR: Could not open file: {file_name}: {err}");
+                }
             }
-        } else {
-            Document::default()
-        };
+        }
+        if buffers.is_empty() {
+            buffers.push(Buffer::default());
+        }
+        Self::new(terminal, config, buffers, initial_status)
+    }
+
+    /// Creates the editor showing the first of the files, which must not be empty.
+    fn new(
+        terminal: Terminal,
+        config: Config,
+        mut buffers: Vec<Buffer>,
+        mut initial_status: String,
+    ) -> Self {
+        let document = mem::take(&mut buffers[0].document);
+
+        let clipboard = clipboard::provider(&config.clipboard).unwrap_or_else(|err| {
+            initial_status = format!("ERR: {err}");
+            None
+        });
+        let keymap = Keymap::new(&config.keys).unwrap_or_else(|err| {
+            initial_status = format!("ERR: {err}");
+            Keymap::default()
+        });
+        if initial_status.is_empty() {
+            initial_status = help(&keymap);
+        }
 
         Self {
             should_quit: false,
             terminal,
             cursor_position: Position::default(),
             offset: Position::default(),
+            offset_line: 0,
             document,
+            buffers,
+            current: 0,
+            panes: vec![Pane::default()],
+            pane: 0,
+            layout: Layout::Pane(0),
             status_message: StatusMessage::from(initial_status),
-            quit_times: QUIT_TIMES,
+            quit_times: config.quit_times,
+            discard_command: None,
+            search_options: SearchOptions::default(),
+            search_highlight: None,
+            selection: None,
+            picker: None,
+            register: String::new(),
+            clipboard,
+            last_input: Instant::now(),
+            keymap,
+            pending_keys: Vec::new(),
+            line_numbers: config.line_numbers,
+            soft_wrap: config.soft_wrap,
+            config,
         }
     }
 
     pub fn run(&mut self) {
         loop {
+            self.load_lines();
             if let Err(err) = self.refresh_screen() {
                 die(&err);
             }
@@ -64,64 +161,259 @@ impl Editor {
         }
     }
 
-    fn refresh_screen(&self) -> Result<(), io::Error> {
+    fn refresh_screen(&mut self) -> Result<(), io::Error> {
         Terminal::cursor_hide();
         Terminal::cursor_position(&Position::default());
         if self.should_quit {
             Terminal::clear_screen();
             println!("Goodbye.\r");
         } else {
-            self.draw_rows();
-            self.draw_status_bar();
+            let (panes, separators) = self.layout.areas(self.screen_area());
+            let focused = self.pane;
+            for (pane, _) in panes {
+                self.with_pane(pane, |editor| editor.draw_pane(pane == focused));
+            }
+            self.draw_separators(&separators);
+            self.draw_picker();
             self.draw_message_bar();
+            let area = self.area();
             Terminal::cursor_position(&Position {
-                x: self.cursor_position.x.saturating_sub(self.offset.x),
-                y: self.cursor_position.y.saturating_sub(self.offset.y),
+                x: self
+                    .cursor_column()
+                    .saturating_sub(self.offset.x)
+                    .saturating_add(self.gutter_width())
+                    .saturating_add(area.x),
+                y: self.cursor_screen_row().saturating_add(area.y),
             });
         }
         Terminal::cursor_show();
         Terminal::flush()
     }
 
+    /// Draws the text and the status line of the focused pane.
+    fn draw_pane(&mut self, is_focused: bool) {
+        if !is_focused {
+            // The file may have changed in another pane or the screen been resized.
+            self.scroll();
+        }
+        let height = self.text_height();
+        self.document
+            .highlight(self.offset.y..self.offset.y.saturating_add(height));
+        self.draw_rows();
+        self.draw_status_bar(is_focused);
+    }
+
     fn draw_rows(&self) {
-        let height = self.terminal.size().height;
+        let area = self.area();
+        let height = self.text_height();
+        let mut lines = self.screen_lines().into_iter();
         for terminal_row in 0..height {
-            Terminal::clear_current_line();
-            let row_index = terminal_row as usize + self.offset.y;
-            if let Some(row) = self.document.row(row_index) {
-                self.draw_row(row);
+            clear_line(&area, terminal_row);
+            let line = lines.next().and_then(|(row_index, graphemes)| {
+                let row = self.document.row(row_index)?;
+                Some((row, row_index, graphemes))
+            });
+            if let Some((row, row_index, graphemes)) = line {
+                self.draw_row(row, row_index, graphemes);
             } else if self.document.is_empty() && terminal_row == height / 3 {
                 self.draw_welcome_message();
             } else {
-                println!("~\r");
+                print!("~");
             }
         }
     }
 
-    fn draw_row(&self, row: &Row) {
-        let start = self.offset.x;
+    fn draw_separators(&self, separators: &[Area]) {
+        Terminal::set_fg_color(self.config.colors.gutter_fg.0);
+        for separator in separators {
+            for y in separator.y..separator.y + separator.height {
+                Terminal::cursor_position(&Position { x: separator.x, y });
+                print!("\u{2502}");
+            }
+        }
+        Terminal::reset_fg_color();
+    }
+
+    /// Draws the entries matching the picker input over the bottom of the text area,
+    /// leaving the terminal cursor at the status bar.
+    fn draw_picker(&self) {
+        let Some(picker) = &self.picker else {
+            return;
+        };
+        let height = self.terminal.size().height as usize;
         let width = self.terminal.size().width as usize;
-        let row = row.render(start, start + width);
-        println!("{row}\r");
+        let shown = picker.matches.len().min(height / 2);
+        // Scroll the list so that the selected entry stays visible.
+        let first = (picker.selected + 1).saturating_sub(shown);
+        Terminal::cursor_position(&Position {
+            x: 0,
+            y: height - shown,
+        });
+        for (i, &index) in picker.matches.iter().enumerate().skip(first).take(shown) {
+            Terminal::clear_current_line();
+            let entry = &picker.entries[index];
+            let text = format!(" {} {}", pad_to_width(&entry.name, 16), entry.description);
+            let padding = width.saturating_sub(text.width() + entry.note.width() + 1);
+            let line = format!("{text}{}{} ", " ".repeat(padding), entry.note);
+            if i == picker.selected {
+                Terminal::set_inverted();
+            }
+            print!("{}", truncate_to_width(&line, width));
+            Terminal::reset_inverted();
+            println!("\r");
+        }
+    }
+
+    /// Returns the row index and the grapheme range of each line of the screen.
+    fn screen_lines(&self) -> Vec<(usize, Range<usize>)> {
+        let height = self.text_height();
+        let mut lines = Vec::with_capacity(height);
+        let mut skip = if self.soft_wrap { self.offset_line } else { 0 };
+        for row_index in self.offset.y..self.document.len() {
+            if lines.len() >= height {
+                break;
+            }
+            let len = self.document.row(row_index).map_or(0, Row::len);
+            if !self.soft_wrap {
+                lines.push((row_index, 0..len));
+                continue;
+            }
+            let starts = self.line_starts(row_index);
+            let ends = starts.iter().skip(1).copied().chain([len]);
+            for (start, end) in starts.iter().copied().zip(ends).skip(skip) {
+                lines.push((row_index, start..end));
+            }
+            skip = 0;
+        }
+        lines.truncate(height);
+        lines
+    }
+
+    fn draw_row(&self, row: &Row, row_index: usize, graphemes: Range<usize>) {
+        // Only the first line of a wrapped row is numbered.
+        self.draw_line_number(row_index, graphemes.start == 0);
+        let start = self.offset.x;
+        let width = self.text_width();
+        let overlays = self.overlays(row, row_index);
+        let columns = start..start + width;
+        for span in row.render_spans(graphemes, columns, self.config.tab_width, &overlays) {
+            if let Some(color) = span.hl_type.to_color() {
+                Terminal::set_fg_color(color);
+            } else {
+                Terminal::reset_fg_color();
+            }
+            Terminal::reset_bg_color();
+            Terminal::reset_inverted();
+            match span.overlay.map(Overlay::to_bg_color) {
+                Some(Some(color)) => Terminal::set_bg_color(color),
+                Some(None) => Terminal::set_inverted(),
+                None => {}
+            }
+            print!("{}", span.text);
+        }
+        Terminal::reset_fg_color();
+        Terminal::reset_bg_color();
+        Terminal::reset_inverted();
+    }
+
+    fn draw_line_number(&self, row_index: usize, is_numbered: bool) {
+        let gutter_width = self.gutter_width();
+        if gutter_width == 0 {
+            return;
+        }
+        if !is_numbered {
+            print!("{}", " ".repeat(gutter_width));
+            return;
+        }
+        let cursor_y = self.cursor_position.y;
+        let number = match self.line_numbers {
+            LineNumbers::Relative if row_index != cursor_y => row_index.abs_diff(cursor_y),
+            _ => row_index.saturating_add(1),
+        };
+        Terminal::set_fg_color(self.config.colors.gutter_fg.0);
+        print!("{number:>width$} ", width = gutter_width - 1);
+        Terminal::reset_fg_color();
+    }
+
+    /// The number of columns taken by line numbers and the space after them.
+    fn gutter_width(&self) -> usize {
+        if self.line_numbers == LineNumbers::Off {
+            return 0;
+        }
+        self.document.len().max(1).to_string().len() + 1
+    }
+
+    /// The number of columns left for the text of the document.
+    fn text_width(&self) -> usize {
+        self.area().width.saturating_sub(self.gutter_width())
+    }
+
+    /// The number of lines of text shown in the focused pane, above its status line.
+    fn text_height(&self) -> usize {
+        self.area().height.saturating_sub(1)
+    }
+
+    /// The part of the screen divided between the panes, above the message bar.
+    fn screen_area(&self) -> Area {
+        let size = self.terminal.size();
+        Area {
+            x: 0,
+            y: 0,
+            width: size.width as usize,
+            height: size.height as usize + 1,
+        }
+    }
+
+    /// The area of the focused pane, including its status line.
+    fn area(&self) -> Area {
+        let screen = self.screen_area();
+        self.layout.area_of(self.pane, screen).unwrap_or(screen)
+    }
+
+    /// Returns the grapheme ranges of the row to draw with overlays, the later taking precedence.
+    fn overlays(&self, row: &Row, row_index: usize) -> Vec<(Range<usize>, Overlay)> {
+        let mut overlays = Vec::new();
+        if let Some(search) = &self.search_highlight {
+            for range in row.find_all(&search.query) {
+                let is_current = search
+                    .current
+                    .as_ref()
+                    .is_some_and(|at| at.y == row_index && at.x == range.start);
+                let overlay = if is_current {
+                    Overlay::CurrentMatch
+                } else {
+                    Overlay::Match
+                };
+                overlays.push((range, overlay));
+            }
+        }
+        if let Some((start, end)) = self.selection_range() {
+            if (start.y..=end.y).contains(&row_index) {
+                let from = if row_index == start.y { start.x } else { 0 };
+                let to = if row_index == end.y { end.x } else { row.len() };
+                overlays.push((from..to, Overlay::Selection));
+            }
+        }
+        overlays
     }
 
     fn draw_welcome_message(&self) {
-        let mut msg = format!("Hecto editor -- versoin {VERSION}\r");
-        let width = self.terminal.size().width as usize;
+        let mut msg = format!("Hecto editor -- versoin {VERSION}");
+        let width = self.area().width;
         let padding = width.saturating_sub(msg.len()) / 2;
         let spaces = " ".repeat(padding.saturating_sub(1));
         msg = format!("~{spaces}{msg}");
         msg.truncate(width);
-        println!("{msg}\r");
+        print!("{msg}");
     }
 
-    fn draw_status_bar(&self) {
-        let mut file_name = self
-            .document
-            .file_name
-            .clone()
-            .unwrap_or_else(|| "[No Name]".to_string());
-        file_name.truncate(20);
+    fn draw_status_bar(&self, is_focused: bool) {
+        let area = self.area();
+        if area.height == 0 {
+            return;
+        }
+        let file_name = self.document.file_name.as_deref().unwrap_or("[No Name]");
+        let file_name = truncate_to_width(file_name, 20);
 
         let modified = if self.document.is_dirty() {
             " (modified)"
@@ -129,85 +421,759 @@ impl Editor {
             ""
         };
 
-        let mut status = format!("{} - {} lines{}", file_name, self.document.len(), modified);
+        let buffer = if self.buffers.len() > 1 {
+            format!(" [{}/{}]", self.current + 1, self.buffers.len())
+        } else {
+            String::new()
+        };
+
+        let mut status = format!(
+            "{} - {} lines{}{}",
+            file_name,
+            self.document.len(),
+            modified,
+            buffer
+        );
 
+        let final_newline = if self.document.has_final_newline() {
+            ""
+        } else {
+            " noeol"
+        };
+        let indexing = self
+            .document
+            .indexing_progress()
+            .map(|percent| format!(" | indexing {percent}%"))
+            .unwrap_or_default();
         let line_indicator = format!(
-            "{}/{}",
+            "{} | {} | {}{} | {}/{}{}",
+            self.document.file_type(),
+            self.document.encoding().name(),
+            self.document.line_ending().name(),
+            final_newline,
             self.cursor_position.y.saturating_add(1),
-            self.document.len()
+            self.document.len(),
+            indexing
         );
-        let len = status.len() + line_indicator.len();
+        let len = status.width() + line_indicator.width();
 
-        let width = self.terminal.size().width as usize;
+        let width = area.width;
         if len < width {
             status.push_str(&" ".repeat(width - len));
         }
         status = format!("{status}{line_indicator}");
-        status.truncate(width);
+        let status = truncate_to_width(&status, width);
 
-        Terminal::set_bg_color(STATUS_BG_COLOR);
-        Terminal::set_fg_color(STATUS_FG_COLOR);
-        println!("{status}\r");
+        let colors = &self.config.colors;
+        let fg = if is_focused {
+            colors.status_fg
+        } else {
+            colors.gutter_fg
+        };
+        Terminal::cursor_position(&Position {
+            x: area.x,
+            y: area.y + area.height - 1,
+        });
+        Terminal::set_bg_color(colors.status_bg.0);
+        Terminal::set_fg_color(fg.0);
+        print!("{status}");
         Terminal::reset_fg_color();
         Terminal::reset_bg_color();
     }
 
     fn draw_message_bar(&self) {
+        Terminal::cursor_position(&Position {
+            x: 0,
+            y: self.screen_area().height,
+        });
         Terminal::clear_current_line();
         let message = &self.status_message;
-        if message.time.elapsed() < Duration::from_secs(5) {
-            let mut text = message.text.clone();
-            text.truncate(self.terminal.size().width as usize);
-            print!("{text}");
+        if message.time.elapsed() < self.config.message_timeout() {
+            let width = self.terminal.size().width as usize;
+            print!("{}", truncate_to_width(&message.text, width));
+        }
+    }
+
+    /// Returns when the screen or the document has to be updated without any input.
+    fn next_deadline(&self) -> Option<Instant> {
+        let message = &self.status_message;
+        let timeout = self.config.message_timeout();
+        let message_expiry = (!message.text.is_empty() && message.time.elapsed() < timeout)
+            .then(|| message.time + timeout);
+        // Redraw regularly to show the lines found in large files.
+        let indexing = self
+            .documents()
+            .any(|document| document.indexing_progress().is_some())
+            .then(|| Instant::now() + INDEXING_REFRESH);
+        [message_expiry, self.autosave_deadline(), indexing]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
+    /// Adds the lines found in the background to the files opened lazily.
+    fn load_lines(&mut self) {
+        let documents = iter::once(&mut self.document)
+            .chain(self.buffers.iter_mut().map(|buffer| &mut buffer.document));
+        for document in documents {
+            match document.load_lines() {
+                Ok(None) => {}
+                Ok(Some(warning)) => self.status_message = StatusMessage::from(warning),
+                Err(err) => {
+                    self.status_message =
+                        StatusMessage::from(format!("ERR: Could not read file: {err}"));
+                }
+            }
+        }
+    }
+
+    fn autosave_deadline(&self) -> Option<Instant> {
+        let delay = self.config.autosave()?;
+        self.documents()
+            .any(needs_autosave)
+            .then(|| self.last_input + delay)
+    }
+
+    fn run_timers(&mut self) {
+        if self
+            .autosave_deadline()
+            .is_some_and(|deadline| deadline <= Instant::now())
+        {
+            // Wait for another delay before retrying if saving fails.
+            self.last_input = Instant::now();
+            let documents = iter::once(&mut self.document)
+                .chain(self.buffers.iter_mut().map(|buffer| &mut buffer.document));
+            let mut result = Ok(None);
+            for document in documents.filter(|document| needs_autosave(document)) {
+                result = match (result, document.save()) {
+                    (Ok(warning), Ok(new_warning)) => Ok(warning.or(new_warning)),
+                    (Err(err), _) | (_, Err(err)) => Err(err),
+                };
+            }
+            self.status_message = match result {
+                Ok(None) => StatusMessage::from("Autosaved.".to_string()),
+                Ok(Some(warning)) => StatusMessage::from(format!("Autosaved, but {warning}")),
+                Err(err) => StatusMessage::from(format!("Error autosaving file! {err}")),
+            };
         }
     }
 
     fn process_keypress(&mut self) -> Result<(), io::Error> {
-        let pressed_key = Terminal::read_key()?;
-        match pressed_key {
-            Key::Ctrl('q') => {
-                if self.document.is_dirty() && self.quit_times > 0 {
-                    self.status_message = StatusMessage::from(format!(
-                        "WARNING! File has unsaved changes. Press Ctrl-Q {} more times to quit.",
-                        self.quit_times
-                    ));
-                    self.quit_times -= 1;
-                    return Ok(());
+        let event = match self.next_deadline() {
+            Some(deadline) => self.terminal.read_event_until(deadline)?,
+            None => Some(self.terminal.read_event()?),
+        };
+        let Some(event) = event else {
+            self.run_timers();
+            return Ok(());
+        };
+        self.last_input = Instant::now();
+        match event {
+            Event::Key(key) => self.process_key(key),
+            Event::Shift(key) => {
+                self.reset_quit_times();
+                self.extend_selection(key);
+            }
+            Event::Paste(text) => {
+                self.reset_quit_times();
+                self.insert_block(&text);
+            }
+            Event::Resize => Terminal::clear_screen(),
+        }
+        self.scroll();
+        Ok(())
+    }
+
+    /// Runs the command bound to the keys pressed so far, or types the character
+    /// if a single unbound character key was pressed.
+    fn process_key(&mut self, pressed_key: Key) {
+        self.pending_keys.push(pressed_key);
+        let command = match self.keymap.lookup(&self.pending_keys) {
+            Lookup::Prefix => {
+                let keys = keymap::describe(&self.pending_keys);
+                self.status_message = StatusMessage::from(format!("{keys}-"));
+                return;
+            }
+            Lookup::Command(command) => command,
+            Lookup::Unbound => {
+                let keys = std::mem::take(&mut self.pending_keys);
+                self.reset_quit_times();
+                match keys.as_slice() {
+                    [Key::Char(c)] => self.insert_char(*c),
+                    [_] => {}
+                    _ => {
+                        let keys = keymap::describe(&keys);
+                        self.status_message = StatusMessage::from(format!("{keys} is not bound."));
+                    }
                 }
-                self.should_quit = true;
+                return;
             }
-            Key::Ctrl('s') => self.save(),
-            Key::Ctrl('f') => self.search(),
-            Key::Char(c) => {
-                self.document.insert(&self.cursor_position, c);
-                self.move_cursor(Key::Right);
+        };
+        self.pending_keys.clear();
+        self.execute(command);
+    }
+
+    fn execute(&mut self, command: Command) {
+        // The palette runs another command, which decides.
+        if !matches!(
+            command,
+            Command::Quit | Command::CloseBuffer | Command::Palette
+        ) {
+            self.reset_quit_times();
+        }
+        match command {
+            Command::Quit => self.quit(),
+            Command::Save => self.save(),
+            Command::Find => self.search(),
+            Command::Replace => self.replace(),
+            Command::Undo => self.undo(),
+            Command::Redo => self.redo(),
+            Command::ConvertLineEndings => self.convert_line_endings(),
+            Command::Copy => self.copy(),
+            Command::Cut => self.cut(),
+            Command::Paste => self.paste(),
+            Command::ToggleMark => self.toggle_mark(),
+            Command::ClearSelection => self.selection = None,
+            Command::Indent if self.selection_range().is_some() => self.indent(false),
+            Command::Indent => self.insert_char('\t'),
+            Command::Outdent => self.indent(true),
+            Command::DeleteForward | Command::DeleteBackward
+                if self.selection_range().is_some() =>
+            {
+                self.delete_selection();
             }
-            Key::Delete => self.document.delete(&self.cursor_position),
-            Key::Backspace => {
+            Command::DeleteForward => self.document.delete(&self.cursor_position),
+            Command::DeleteBackward => {
                 if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
                     self.move_cursor(Key::Left);
                     self.document.delete(&self.cursor_position);
                 }
             }
-            Key::Up
-            | Key::Down
-            | Key::Left
-            | Key::Right
-            | Key::PageUp
-            | Key::PageDown
-            | Key::Home
-            | Key::End => self.move_cursor(pressed_key),
-            _ => {}
+            Command::CursorUp => self.motion(Key::Up),
+            Command::CursorDown => self.motion(Key::Down),
+            Command::CursorLeft => self.motion(Key::Left),
+            Command::CursorRight => self.motion(Key::Right),
+            Command::PageUp => self.motion(Key::PageUp),
+            Command::PageDown => self.motion(Key::PageDown),
+            Command::LineStart => self.motion(Key::Home),
+            Command::LineEnd => self.motion(Key::End),
+            Command::ToggleLineNumbers => self.line_numbers = self.line_numbers.next(),
+            Command::ToggleSoftWrap => self.toggle_soft_wrap(),
+            Command::Palette => self.command_palette(),
+            Command::NextBuffer => self.cycle_buffer(false),
+            Command::PreviousBuffer => self.cycle_buffer(true),
+            Command::ListBuffers => self.list_buffers(),
+            Command::OpenFile => self.open_file(),
+            Command::CloseBuffer => self.close_buffer(),
+            Command::SplitHorizontal => self.split(Direction::Horizontal),
+            Command::SplitVertical => self.split(Direction::Vertical),
+            Command::ClosePane => self.close_pane(),
+            Command::NextPane => self.next_pane(),
         }
-        self.scroll();
-        if self.quit_times < QUIT_TIMES {
-            self.quit_times = QUIT_TIMES;
+    }
+
+    /// Prompts for a command, listing the commands matching the input.
+    fn command_palette(&mut self) {
+        let entries = COMMANDS
+            .iter()
+            .map(|info| {
+                let keys: Vec<String> = self
+                    .keymap
+                    .keys_for(info.command)
+                    .into_iter()
+                    .map(keymap::describe)
+                    .collect();
+                PickerEntry {
+                    name: info.name.to_string(),
+                    description: info.description.to_string(),
+                    note: keys.join(", "),
+                }
+            })
+            .collect();
+        if let Some(index) = self.pick("Command", entries) {
+            self.execute(COMMANDS[index].command);
+        }
+    }
+
+    /// Prompts for one of the entries, listing the entries matching the input,
+    /// and returns the index of the chosen one.
+    fn pick(&mut self, label: &str, entries: Vec<PickerEntry>) -> Option<usize> {
+        self.picker = Some(Picker::new(entries));
+        let label = format!("{label} (Up/Down = select): ");
+        let result = self.prompt_with_label(
+            |_, _| label.clone(),
+            |editor, key, query| {
+                let Some(picker) = &mut editor.picker else {
+                    return;
+                };
+                match key {
+                    Key::Up => picker.selected = picker.selected.saturating_sub(1),
+                    Key::Down => {
+                        let last = picker.matches.len().saturating_sub(1);
+                        picker.selected = (picker.selected + 1).min(last);
+                    }
+                    _ => picker.filter(query),
+                }
+            },
+        );
+        let picker = self.picker.take()?;
+        result.ok()??;
+        picker.matches.get(picker.selected).copied()
+    }
+
+    fn quit(&mut self) {
+        let unsaved = self
+            .documents()
+            .filter(|document| document.is_dirty())
+            .count();
+        let warning = match unsaved {
+            0 => {
+                self.should_quit = true;
+                return;
+            }
+            1 if self.document.is_dirty() => String::from("File has unsaved changes."),
+            1 => String::from("Another file has unsaved changes."),
+            _ => format!("{unsaved} files have unsaved changes."),
+        };
+        if self.confirm_discard(Command::Quit, &warning, "quit") {
+            self.should_quit = true;
+        }
+    }
+
+    /// Warns that unsaved changes would be lost until the command was repeated
+    /// `quit_times` times, and returns whether to go on.
+    fn confirm_discard(&mut self, command: Command, warning: &str, action: &str) -> bool {
+        // Each command counts down on its own.
+        if self.discard_command != Some(command) {
+            self.discard_command = Some(command);
+            self.quit_times = self.config.quit_times;
+        }
+        if self.quit_times == 0 {
+            return true;
+        }
+        let key = self
+            .keymap
+            .keys_for(command)
+            .first()
+            .map_or_else(|| String::from(action), |keys| keymap::describe(keys));
+        self.status_message = StatusMessage::from(format!(
+            "WARNING! {warning} Press {key} {} more times to {action}.",
+            self.quit_times
+        ));
+        self.quit_times -= 1;
+        false
+    }
+
+    /// Returns the documents of all open files.
+    fn documents(&self) -> impl Iterator<Item = &Document> {
+        // The slot of the current file is empty, so it is never dirty.
+        iter::once(&self.document).chain(self.buffers.iter().map(|buffer| &buffer.document))
+    }
+
+    fn buffer_document(&self, index: usize) -> &Document {
+        if index == self.current {
+            &self.document
+        } else {
+            &self.buffers[index].document
+        }
+    }
+
+    /// Shows another open file in the focused pane, keeping the cursor and the scroll
+    /// position of the file left.
+    fn switch_buffer(&mut self, index: usize) {
+        if index == self.current || index >= self.buffers.len() {
+            return;
+        }
+        self.buffers[self.current] = Buffer {
+            document: mem::take(&mut self.document),
+            view: self.take_view(),
+        };
+        let buffer = mem::take(&mut self.buffers[index]);
+        self.current = index;
+        self.panes[self.pane].buffer = index;
+        self.load_buffer(buffer);
+    }
+
+    fn load_buffer(&mut self, buffer: Buffer) {
+        self.document = buffer.document;
+        self.load_view(buffer.view);
+        self.selection = None;
+        self.search_highlight = None;
+    }
+
+    /// Moves the cursor and the scroll position of the focused pane out of the editor.
+    fn take_view(&mut self) -> View {
+        View {
+            cursor_position: mem::take(&mut self.cursor_position),
+            offset: mem::take(&mut self.offset),
+            offset_line: mem::take(&mut self.offset_line),
+        }
+    }
+
+    /// Restores a view of the current file, keeping the cursor inside the file
+    /// in case it was edited in another pane.
+    fn load_view(&mut self, view: View) {
+        let mut cursor_position = view.cursor_position;
+        cursor_position.y = cursor_position.y.min(self.document.len());
+        let len = self.document.row(cursor_position.y).map_or(0, Row::len);
+        cursor_position.x = cursor_position.x.min(len);
+        self.cursor_position = cursor_position;
+        self.offset = view.offset;
+        self.offset_line = view.offset_line;
+    }
+
+    fn cycle_buffer(&mut self, backward: bool) {
+        let len = self.buffers.len();
+        if len == 1 {
+            self.status_message = StatusMessage::from("No other open files.".to_string());
+            return;
+        }
+        let index = if backward {
+            (self.current + len - 1) % len
+        } else {
+            (self.current + 1) % len
+        };
+        self.switch_buffer(index);
+    }
+
+    fn list_buffers(&mut self) {
+        let entries = (0..self.buffers.len())
+            .map(|index| {
+                let document = self.buffer_document(index);
+                let modified = if document.is_dirty() {
+                    " (modified)"
+                } else {
+                    ""
+                };
+                PickerEntry {
+                    name: document
+                        .file_name
+                        .clone()
+                        .unwrap_or_else(|| "[No Name]".to_string()),
+                    description: format!("{} lines{modified}", document.len()),
+                    note: format!("{}", index + 1),
+                }
+            })
+            .collect();
+        if let Some(index) = self.pick("Buffer", entries) {
+            self.switch_buffer(index);
+        }
+    }
+
+    /// Prompts for a file name and opens the file, or starts a new document
+    /// saved under that name if the file does not exist.
+    fn open_file(&mut self) {
+        let Some(file_name) = self.prompt("Open: ", |_, _, _| {}).unwrap_or(None) else {
+            return;
+        };
+        let open = (0..self.buffers.len())
+            .find(|&index| self.buffer_document(index).file_name.as_ref() == Some(&file_name));
+        if let Some(index) = open {
+            self.switch_buffer(index);
+            return;
+        }
+        let document = match open_document(&file_name, &self.config) {
+            Ok(document) => document,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                self.status_message = StatusMessage::from(format!("New file: {file_name}"));
+                let mut document = Document::default();
+                document.file_name = Some(file_name);
+                document
+            }
+            Err(err) => {
+                self.status_message =
+                    StatusMessage::from(format!("ERR: Could not open file: {file_name}: {err}"));
+                return;
+            }
+        };
+        self.buffers.push(Buffer {
+            document,
+            ..Buffer::default()
+        });
+        self.switch_buffer(self.buffers.len() - 1);
+    }
+
+    /// Closes the current file, asking to repeat the command if it has unsaved changes.
+    /// Closing the last file leaves an empty document.
+    fn close_buffer(&mut self) {
+        if self.document.is_dirty()
+            && !self.confirm_discard(
+                Command::CloseBuffer,
+                "File has unsaved changes.",
+                "close it",
+            )
+        {
+            return;
+        }
+        self.reset_quit_times();
+        if self.buffers.len() == 1 {
+            self.load_buffer(Buffer::default());
+            return;
+        }
+        let closed = self.current;
+        self.buffers.remove(closed);
+        self.current = closed.min(self.buffers.len() - 1);
+        let buffer = mem::take(&mut self.buffers[self.current]);
+        self.load_buffer(buffer);
+        // Panes showing the closed file show the new current one instead.
+        for pane in &mut self.panes {
+            match pane.buffer.cmp(&closed) {
+                Ordering::Equal => {
+                    pane.buffer = self.current;
+                    pane.view = View::default();
+                }
+                Ordering::Greater => pane.buffer -= 1,
+                Ordering::Less => {}
+            }
+        }
+    }
+
+    /// Divides the focused pane in two, both showing the current file.
+    fn split(&mut self, direction: Direction) {
+        if !self.area().can_split(direction) {
+            self.status_message = StatusMessage::from("Not enough room to split.".to_string());
+            return;
+        }
+        let pane = Pane {
+            buffer: self.current,
+            view: View {
+                cursor_position: self.cursor_position.clone(),
+                offset: self.offset.clone(),
+                offset_line: self.offset_line,
+            },
+        };
+        self.panes.push(pane);
+        self.layout
+            .split(self.pane, self.panes.len() - 1, direction);
+    }
+
+    /// Closes the focused pane, leaving its file open.
+    fn close_pane(&mut self) {
+        let closed = self.pane;
+        let Some(next) = self.layout.remove(closed) else {
+            self.status_message = StatusMessage::from("Cannot close the only pane.".to_string());
+            return;
+        };
+        self.panes.remove(closed);
+        self.selection = None;
+        self.search_highlight = None;
+        // The view of the closed pane is dropped instead of being stored.
+        self.enter_pane(next);
+    }
+
+    /// Moves the focus to the next pane in screen order.
+    fn next_pane(&mut self) {
+        let (panes, _) = self.layout.areas(self.screen_area());
+        let order: Vec<usize> = panes.into_iter().map(|(pane, _)| pane).collect();
+        let Some(position) = order.iter().position(|&pane| pane == self.pane) else {
+            return;
+        };
+        let next = order[(position + 1) % order.len()];
+        if next == self.pane {
+            self.status_message = StatusMessage::from("No other panes.".to_string());
+            return;
+        }
+        self.selection = None;
+        self.search_highlight = None;
+        self.focus_pane(next);
+    }
+
+    fn focus_pane(&mut self, index: usize) {
+        if index == self.pane {
+            return;
+        }
+        self.panes[self.pane].view = self.take_view();
+        self.enter_pane(index);
+    }
+
+    /// Loads the view of the pane, and its file if the pane shows another one.
+    fn enter_pane(&mut self, index: usize) {
+        let buffer = self.panes[index].buffer;
+        if buffer != self.current {
+            self.buffers[self.current].document = mem::take(&mut self.document);
+            self.document = mem::take(&mut self.buffers[buffer].document);
+            self.current = buffer;
+        }
+        self.pane = index;
+        let view = mem::take(&mut self.panes[index].view);
+        self.load_view(view);
+    }
+
+    /// Runs `f` with another pane focused, then focuses the current one again
+    /// with its selection and search highlight.
+    fn with_pane<R>(&mut self, index: usize, f: impl FnOnce(&mut Self) -> R) -> R {
+        if index == self.pane {
+            return f(self);
+        }
+        let focused = self.pane;
+        let selection = self.selection.take();
+        let search_highlight = self.search_highlight.take();
+        self.focus_pane(index);
+        let result = f(self);
+        self.focus_pane(focused);
+        self.selection = selection;
+        self.search_highlight = search_highlight;
+        result
+    }
+
+    /// Forgets the quit attempts made with unsaved changes.
+    fn reset_quit_times(&mut self) {
+        if self.quit_times < self.config.quit_times {
+            self.quit_times = self.config.quit_times;
             self.status_message = StatusMessage::from(String::new());
         }
-        Ok(())
+    }
+
+    fn insert_char(&mut self, c: char) {
+        self.delete_selection();
+        self.document.insert(&self.cursor_position, c);
+        self.move_cursor(Key::Right);
+    }
+
+    /// Moves the cursor, clearing a selection not started with the mark.
+    fn motion(&mut self, key: Key) {
+        if self.selection.as_ref().is_some_and(|s| !s.sticky) {
+            self.selection = None;
+        }
+        self.move_cursor(key);
+    }
+
+    /// Moves the cursor while keeping the selection anchored where it started.
+    fn extend_selection(&mut self, key: Key) {
+        if self.selection.is_none() {
+            self.selection = Some(Selection {
+                anchor: self.cursor_position.clone(),
+                sticky: false,
+            });
+        }
+        self.move_cursor(key);
+    }
+
+    fn toggle_mark(&mut self) {
+        if self.selection.take().is_some() {
+            self.status_message = StatusMessage::from("Mark deactivated.".to_string());
+        } else {
+            self.selection = Some(Selection {
+                anchor: self.cursor_position.clone(),
+                sticky: true,
+            });
+            self.status_message = StatusMessage::from("Mark set.".to_string());
+        }
+    }
+
+    /// Returns the start and the end of the selection, if any text is selected.
+    fn selection_range(&self) -> Option<(Position, Position)> {
+        let anchor = &self.selection.as_ref()?.anchor;
+        let cursor = &self.cursor_position;
+        match (anchor.y, anchor.x).cmp(&(cursor.y, cursor.x)) {
+            Ordering::Equal => None,
+            Ordering::Less => Some((anchor.clone(), cursor.clone())),
+            Ordering::Greater => Some((cursor.clone(), anchor.clone())),
+        }
+    }
+
+    /// Returns the range to copy or cut: the selection, or the current row with its line break.
+    fn copy_range(&self) -> (Position, Position) {
+        self.selection_range().unwrap_or_else(|| {
+            let y = self.cursor_position.y;
+            (Position { x: 0, y }, Position { x: 0, y: y + 1 })
+        })
+    }
+
+    fn copy(&mut self) {
+        let (start, end) = self.copy_range();
+        self.status_message = StatusMessage::from("Copied.".to_string());
+        self.set_register(self.document.text(&start, &end));
+        self.selection = None;
+    }
+
+    fn cut(&mut self) {
+        let (start, end) = self.copy_range();
+        self.set_register(self.document.text(&start, &end));
+        self.document.delete_range(&start, &end);
+        self.cursor_position = start;
+        self.selection = None;
+    }
+
+    /// Stores the copied text and sends it to the system clipboard.
+    fn set_register(&mut self, text: String) {
+        if let Some(clipboard) = &mut self.clipboard {
+            if let Err(err) = clipboard.copy(&text) {
+                self.status_message = StatusMessage::from(format!("Clipboard error: {err}"));
+            }
+        }
+        self.register = text;
+    }
+
+    fn paste(&mut self) {
+        if self.register.is_empty() {
+            return;
+        }
+        self.insert_block(&self.register.clone());
+    }
+
+    /// Inserts the text in place of the selection as a single undoable step.
+    fn insert_block(&mut self, text: &str) {
+        let (start, end) = self
+            .selection_range()
+            .unwrap_or_else(|| (self.cursor_position.clone(), self.cursor_position.clone()));
+        self.cursor_position = self.document.replace_range(&start, &end, text);
+        self.selection = None;
+    }
+
+    fn delete_selection(&mut self) {
+        if let Some((start, end)) = self.selection_range() {
+            self.document.delete_range(&start, &end);
+            self.cursor_position = start;
+        }
+        self.selection = None;
+    }
+
+    /// Indents or outdents the selected rows, or the current row without a selection.
+    fn indent(&mut self, outdent: bool) {
+        let rows = match self.selection_range() {
+            // A selection ending at the start of a row does not include that row.
+            Some((start, end)) if end.x == 0 && end.y > start.y => start.y..end.y,
+            Some((start, end)) => start.y..end.y + 1,
+            None => self.cursor_position.y..self.cursor_position.y + 1,
+        };
+        let row_len = |document: &Document, y: usize| document.row(y).map_or(0, Row::len);
+        let cursor_len = row_len(&self.document, self.cursor_position.y);
+        let anchor_len = self
+            .selection
+            .as_ref()
+            .map(|selection| row_len(&self.document, selection.anchor.y));
+        if outdent {
+            self.document.outdent(rows, INDENT.len());
+        } else {
+            self.document.indent(rows, INDENT);
+        }
+        // Keep the cursor and the anchor on the same characters. A position at the
+        // start of a row stays there so that the selection keeps covering whole rows.
+        let shift = |document: &Document, at: &mut Position, len_before: usize| {
+            if at.x > 0 {
+                at.x = (at.x + row_len(document, at.y)).saturating_sub(len_before);
+            }
+        };
+        shift(&self.document, &mut self.cursor_position, cursor_len);
+        if let (Some(selection), Some(len_before)) = (&mut self.selection, anchor_len) {
+            shift(&self.document, &mut selection.anchor, len_before);
+        }
+    }
+
+    fn toggle_soft_wrap(&mut self) {
+        self.soft_wrap = !self.soft_wrap;
+        self.offset.x = 0;
+        self.offset_line = 0;
+        let state = if self.soft_wrap { "on" } else { "off" };
+        self.status_message = StatusMessage::from(format!("Soft wrap {state}."));
     }
 
     fn move_cursor(&mut self, key: Key) {
+        if self.soft_wrap && matches!(key, Key::Up | Key::Down) {
+            self.move_visual_line(key == Key::Up);
+            return;
+        }
         let Position { mut x, mut y } = self.cursor_position;
         let height = self.document.len();
         let width = if let Some(row) = self.document.row(y) {
@@ -215,14 +1181,10 @@ impl Editor {
         } else {
             0
         };
-        let terminal_height = self.terminal.size().height as usize;
+        let terminal_height = self.text_height();
         match key {
             Key::Up => y = y.saturating_sub(1),
-            Key::Down => {
-                if y < height {
-                    y = y.saturating_add(1);
-                }
-            }
+            Key::Down if y < height => y = y.saturating_add(1),
             Key::Left => {
                 if x > 0 {
                     x -= 1;
@@ -256,6 +1218,16 @@ impl Editor {
             _ => {}
         }
 
+        // Keep the cursor in the same screen column when moving to another row.
+        let is_vertical = matches!(key, Key::Up | Key::Down | Key::PageUp | Key::PageDown);
+        if is_vertical && y != self.cursor_position.y {
+            let column = self.cursor_column();
+            x = self
+                .document
+                .row(y)
+                .map_or(0, |row| row.index(0, column, self.config.tab_width));
+        }
+
         // Prevent x from exceeding the current line width after y is changed.
         let width = if let Some(row) = self.document.row(y) {
             row.len()
@@ -269,11 +1241,94 @@ impl Editor {
         self.cursor_position = Position { x, y }
     }
 
+    /// Moves the cursor to the previous or the next screen line of a wrapped row,
+    /// keeping it in the same screen column.
+    fn move_visual_line(&mut self, up: bool) {
+        let Position { y, .. } = self.cursor_position;
+        let (line, starts) = self.cursor_line();
+        let column = self.cursor_column();
+        let (y, line) = if up {
+            match (line, y) {
+                (0, 0) => return,
+                (0, _) => (y - 1, self.line_starts(y - 1).len() - 1),
+                _ => (y, line - 1),
+            }
+        } else if line + 1 < starts.len() {
+            (y, line + 1)
+        } else if y < self.document.len() {
+            (y + 1, 0)
+        } else {
+            return;
+        };
+        let starts = self.line_starts(y);
+        let mut x = self.document.row(y).map_or(0, |row| {
+            row.index(starts[line], column, self.config.tab_width)
+        });
+        // Stay on this line instead of moving to the start of the next one.
+        if let Some(&next) = starts.get(line + 1) {
+            x = x.min(next.saturating_sub(1));
+        }
+        self.cursor_position = Position { x, y };
+    }
+
+    /// With soft wrap, returns the index of the grapheme starting each screen line of the row.
+    fn line_starts(&self, y: usize) -> Vec<usize> {
+        self.document.row(y).map_or_else(
+            || vec![0],
+            |row| row.wrap(self.text_width(), self.config.tab_width),
+        )
+    }
+
+    /// With soft wrap, returns the screen line of the current row holding the cursor
+    /// and the starts of the lines of the row.
+    fn cursor_line(&self) -> (usize, Vec<usize>) {
+        let starts = self.line_starts(self.cursor_position.y);
+        let x = self.cursor_position.x;
+        let line = starts
+            .partition_point(|&start| start <= x)
+            .saturating_sub(1);
+        (line, starts)
+    }
+
+    /// The screen column of the cursor within the current row, or the current
+    /// screen line with soft wrap, with tabs expanded.
+    fn cursor_column(&self) -> usize {
+        let Position { x, y } = self.cursor_position;
+        let start = if self.soft_wrap {
+            let (line, starts) = self.cursor_line();
+            starts[line]
+        } else {
+            0
+        };
+        self.document
+            .row(y)
+            .map_or(0, |row| row.column(start, x, self.config.tab_width))
+    }
+
+    /// The screen line of the cursor, counted from the top of the screen.
+    fn cursor_screen_row(&self) -> usize {
+        let y = self.cursor_position.y;
+        if !self.soft_wrap {
+            return y.saturating_sub(self.offset.y);
+        }
+        let rows: usize = (self.offset.y..y)
+            .map(|row_index| self.line_starts(row_index).len())
+            .sum();
+        (rows + self.cursor_line().0).saturating_sub(self.offset_line)
+    }
+
     /// Update the cursor offset based on the terminal size and
     /// the current cursor position within the document.
+    /// The horizontal offset is in screen columns.
     fn scroll(&mut self) {
-        let width = self.terminal.size().width as usize;
-        let height = self.terminal.size().height as usize;
+        if self.soft_wrap {
+            self.scroll_wrapped();
+            return;
+        }
+        self.offset_line = 0;
+        let width = self.text_width();
+        let height = self.text_height();
+        let column = self.cursor_column();
         let pos = &self.cursor_position;
         let offset = &mut self.offset;
         if pos.y < offset.y {
@@ -281,10 +1336,39 @@ impl Editor {
         } else if pos.y >= offset.y.saturating_add(height) {
             offset.y = pos.y.saturating_sub(height).saturating_add(1);
         }
-        if pos.x < offset.x {
-            offset.x = pos.x;
-        } else if pos.x >= offset.x.saturating_add(width) {
-            offset.x = pos.x.saturating_sub(width).saturating_add(1);
+        if column < offset.x {
+            offset.x = column;
+        } else if column >= offset.x.saturating_add(width) {
+            offset.x = column.saturating_sub(width).saturating_add(1);
+        }
+    }
+
+    /// Same as `scroll`, but in screen lines of wrapped rows.
+    fn scroll_wrapped(&mut self) {
+        let height = self.text_height();
+        let y = self.cursor_position.y;
+        let line = self.cursor_line().0;
+        self.offset.x = 0;
+        self.offset_line = self
+            .offset_line
+            .min(self.line_starts(self.offset.y).len() - 1);
+        if (y, line) < (self.offset.y, self.offset_line) {
+            self.offset.y = y;
+            self.offset_line = line;
+            return;
+        }
+        // Find the topmost line that still shows the cursor at the bottom of the screen.
+        let (mut top_y, mut top_line) = (y, line);
+        let mut above = height.saturating_sub(1);
+        while above > top_line && top_y > 0 {
+            above -= top_line + 1;
+            top_y -= 1;
+            top_line = self.line_starts(top_y).len() - 1;
+        }
+        top_line = top_line.saturating_sub(above);
+        if (self.offset.y, self.offset_line) < (top_y, top_line) {
+            self.offset.y = top_y;
+            self.offset_line = top_line;
         }
     }
 
@@ -297,22 +1381,74 @@ impl Editor {
             }
             self.document.file_name = file_name;
         }
-        self.status_message = if self.document.save().is_ok() {
-            StatusMessage::from("File saved successfully.".to_string())
+        self.status_message = match self.document.save() {
+            Ok(None) => StatusMessage::from("File saved successfully.".to_string()),
+            Ok(Some(warning)) => StatusMessage::from(format!("File saved, but {warning}")),
+            Err(err) => StatusMessage::from(format!("Error writing file! {err}")),
+        };
+    }
+
+    fn undo(&mut self) {
+        self.selection = None;
+        if let Some(position) = self.document.undo() {
+            self.cursor_position = position;
+        } else {
+            self.status_message = StatusMessage::from("Nothing to undo.".to_string());
+        }
+    }
+
+    fn redo(&mut self) {
+        self.selection = None;
+        if let Some(position) = self.document.redo() {
+            self.cursor_position = position;
         } else {
-            StatusMessage::from("Error writing file!".to_string())
+            self.status_message = StatusMessage::from("Nothing to redo.".to_string());
+        }
+    }
+
+    fn convert_line_endings(&mut self) {
+        let format = self
+            .prompt("Convert to (lf, crlf, eol, noeol): ", |_, _, _| {})
+            .unwrap_or(None);
+        let Some(format) = format else {
+            return;
         };
+        match format.to_lowercase().as_str() {
+            "lf" => self.document.set_line_ending(LineEnding::Lf),
+            "crlf" => self.document.set_line_ending(LineEnding::CrLf),
+            "eol" => self.document.set_final_newline(true),
+            "noeol" => self.document.set_final_newline(false),
+            _ => {
+                self.status_message = StatusMessage::from(format!("Unknown format: {format}"));
+            }
+        }
     }
 
     fn search(&mut self) {
         let mut direction = SearchDirection::Forward;
         let mut last_match_query: Option<String> = None;
         let old_position = self.cursor_position.clone();
-        let query = self.prompt(
-            "Search (ESC to cancel, Arrows to navigate): ",
+        let query = self.prompt_with_label(
+            |editor, query| {
+                let options = editor.search_options;
+                let status = if SearchQuery::new(query, options).is_err() {
+                    String::from("[invalid] ")
+                } else {
+                    editor
+                        .search_highlight
+                        .as_ref()
+                        .map(SearchHighlight::label)
+                        .unwrap_or_default()
+                };
+                format!(
+                    "Search {status}{}(Arrows = navigate, Ctrl-R/C/W = regex/case/word): ",
+                    options.label()
+                )
+            },
             |editor, key, query| {
                 let mut moved = false;
                 match key {
+                    Key::Ctrl('r' | 'c' | 'w') => editor.toggle_search_option(key),
                     Key::Right | Key::Down => {
                         direction = SearchDirection::Forward;
                         // Proceed the position to search the next match.
@@ -322,63 +1458,308 @@ impl Editor {
                     Key::Left | Key::Up => direction = SearchDirection::Backward,
                     _ => direction = SearchDirection::Forward,
                 }
-                let position = editor
-                    .document
-                    .find(query, &editor.cursor_position, direction);
-                if let Some(position) = position {
+                let Ok(search_query) = SearchQuery::new(query, editor.search_options) else {
+                    editor.search_highlight = None;
+                    return;
+                };
+                let position =
+                    editor
+                        .document
+                        .find(&search_query, &editor.cursor_position, direction);
+                if let Some((position, _)) = &position {
                     last_match_query = Some(query.clone());
-                    editor.cursor_position = position;
+                    editor.cursor_position = position.clone();
                     editor.scroll();
                 } else if moved {
                     editor.move_cursor(Key::Left);
                 }
+                let current = position.map(|(position, _)| position);
+                editor.highlight_matches(search_query, current);
             },
         );
+        self.search_highlight = None;
         if let (Ok(Some(query)), Some(last_match_query)) = (query, last_match_query) {
             if query == last_match_query {
                 return;
-            } else {
-                self.status_message = StatusMessage::from(format!("Not found: {query}"));
             }
+            self.status_message = StatusMessage::from(format!("Not found: {query}"));
         }
         self.cursor_position = old_position;
         self.scroll();
     }
 
-    fn prompt<C>(&mut self, prompt: &str, mut callback: C) -> Result<Option<String>, io::Error>
+    fn toggle_search_option(&mut self, key: Key) {
+        let options = &mut self.search_options;
+        match key {
+            Key::Ctrl('r') => options.regex = !options.regex,
+            Key::Ctrl('c') => options.case = options.case.next(),
+            Key::Ctrl('w') => options.whole_word = !options.whole_word,
+            _ => {}
+        }
+    }
+
+    fn highlight_matches(&mut self, query: SearchQuery, current: Option<Position>) {
+        let at = current.as_ref().unwrap_or(&self.cursor_position);
+        // Matches are not counted in large files, which would mean reading all of them.
+        let counts = if query.is_empty() {
+            Some((0, 0))
+        } else {
+            self.document.count_matches(&query, at)
+        };
+        self.search_highlight = Some(SearchHighlight {
+            query,
+            current,
+            index: counts.map_or(0, |(before, _)| before + 1),
+            total: counts.map(|(_, total)| total),
+        });
+    }
+
+    fn replace(&mut self) {
+        let query = self
+            .prompt_with_label(
+                |editor, _| {
+                    format!(
+                        "Replace {}(Ctrl-R/C/W = regex/case/word): ",
+                        editor.search_options.label()
+                    )
+                },
+                |editor, key, _| editor.toggle_search_option(key),
+            )
+            .unwrap_or(None)
+            .filter(|query| !query.is_empty());
+        let Some(query) = query else {
+            return;
+        };
+        let search_query = match SearchQuery::new(&query, self.search_options) {
+            Ok(search_query) => search_query,
+            Err(err) => {
+                self.status_message = StatusMessage::from(format!("Invalid regex: {err}"));
+                return;
+            }
+        };
+        let replace_with = format!("Replace {query} with: ");
+        let Ok(Some(replacement)) =
+            self.prompt_with_label(|_, _| replace_with.clone(), |_, _, _| {})
+        else {
+            return;
+        };
+
+        let old_position = self.cursor_position.clone();
+        let mut at = self.cursor_position.clone();
+        let mut count = 0;
+        let mut replace_all = false;
+        while let Some((position, len)) =
+            self.document
+                .find(&search_query, &at, SearchDirection::Forward)
+        {
+            self.cursor_position = position.clone();
+            self.scroll();
+            if !replace_all {
+                self.highlight_matches(search_query.clone(), Some(position.clone()));
+                let counter = self
+                    .search_highlight
+                    .as_ref()
+                    .map(SearchHighlight::label)
+                    .unwrap_or_default();
+                self.status_message =
+                    StatusMessage::from(format!("Replace? {counter}(y)es / (n)o / (a)ll / (q)uit"));
+                if self.refresh_screen().is_err() {
+                    break;
+                }
+                match self.terminal.read_event() {
+                    Ok(Event::Key(Key::Char('y'))) => {}
+                    Ok(Event::Key(Key::Char('a'))) => replace_all = true,
+                    Ok(Event::Key(Key::Char('n'))) => {
+                        at = Position {
+                            x: position.x.saturating_add(len.max(1)),
+                            y: position.y,
+                        };
+                        continue;
+                    }
+                    Ok(Event::Key(Key::Char('q') | Key::Esc)) | Err(_) => break,
+                    Ok(Event::Resize) => {
+                        Terminal::clear_screen();
+                        continue;
+                    }
+                    Ok(_) => continue,
+                }
+            }
+            let Some((matched, text)) = self.document.row(position.y).and_then(|row| {
+                let matched = row.render(position.x, position.x.saturating_add(len));
+                let start = row.byte_index(position.x);
+                let text = search_query.replacement(row.as_str(), start, &replacement)?;
+                Some((matched, text))
+            }) else {
+                break;
+            };
+            // A replacement that changes nothing is not counted, nor is the file modified.
+            if text != matched {
+                self.document.replace(&position, len, &text);
+                count += 1;
+            }
+            let text_len = text.graphemes(true).count();
+            // Step over an empty match so that it is not replaced forever.
+            let step = if len == 0 { text_len + 1 } else { text_len };
+            at = Position {
+                x: position.x.saturating_add(step),
+                y: position.y,
+            };
+        }
+        self.search_highlight = None;
+        if count == 0 {
+            self.cursor_position = old_position;
+        }
+        self.scroll();
+        self.status_message = StatusMessage::from(format!("Replaced {count} occurrence(s)."));
+    }
+
+    fn prompt<C>(&mut self, prompt: &str, callback: C) -> Result<Option<String>, io::Error>
     where
         C: FnMut(&mut Self, Key, &String),
+    {
+        let result = self.prompt_with_label(|_, _| prompt.to_string(), callback)?;
+        Ok(result.filter(|result| !result.is_empty()))
+    }
+
+    /// Same as `prompt`, but the label is rebuilt on every key press
+    /// so that it can reflect state changed by the callback.
+    /// An empty input is returned as is, and `None` means the prompt was cancelled.
+    fn prompt_with_label<L, C>(
+        &mut self,
+        label: L,
+        mut callback: C,
+    ) -> Result<Option<String>, io::Error>
+    where
+        L: Fn(&Self, &str) -> String,
+        C: FnMut(&mut Self, Key, &String),
     {
         let mut result = String::new();
         loop {
+            let prompt = label(self, &result);
             self.status_message = StatusMessage::from(format!("{prompt}{result}"));
             self.refresh_screen()?;
-            let key = Terminal::read_key()?;
+            let key = match self.terminal.read_event()? {
+                Event::Key(key) => key,
+                // Pasted text is taken as if it was typed, up to the first line break.
+                Event::Paste(text) => {
+                    let line = text.lines().next().unwrap_or_default();
+                    let mut chars = line.chars().filter(|c| !c.is_control());
+                    let Some(last) = chars.next_back() else {
+                        continue;
+                    };
+                    result.extend(chars);
+                    Key::Char(last)
+                }
+                Event::Resize => {
+                    Terminal::clear_screen();
+                    self.scroll();
+                    continue;
+                }
+                Event::Shift(_) => continue,
+            };
             match key {
                 Key::Esc => {
-                    result.truncate(0);
-                    break;
+                    callback(self, key, &String::new());
+                    self.status_message = StatusMessage::from(String::new());
+                    return Ok(None);
                 }
                 Key::Backspace => {
-                    if !result.is_empty() {
-                        result.truncate(result.len() - 1);
-                    }
+                    result.pop();
                 }
                 Key::Char('\n') => break,
-                Key::Char(c) => {
-                    if !c.is_control() {
-                        result.push(c);
-                    }
-                }
+                Key::Char(c) if !c.is_control() => result.push(c),
                 _ => {}
             }
             callback(self, key, &result);
         }
         self.status_message = StatusMessage::from(String::new());
-        if result.is_empty() {
-            Ok(None)
-        } else {
-            Ok(Some(result))
+        Ok(Some(result))
+    }
+}
+
+/// The anchor of a selection. The selection spans from the anchor to the cursor.
+struct Selection {
+    anchor: Position,
+    /// Whether the selection was started with the mark key and survives cursor motions.
+    sticky: bool,
+}
+
+/// An open file with the view last used on it.
+#[derive(Default)]
+struct Buffer {
+    document: Document,
+    view: View,
+}
+
+/// The cursor and the scroll position in a file.
+#[derive(Default)]
+struct View {
+    cursor_position: Position,
+    offset: Position,
+    /// With soft wrap, the first visual line shown of the row at `offset.y`.
+    offset_line: usize,
+}
+
+/// A part of the screen showing one of the open files.
+#[derive(Default)]
+struct Pane {
+    /// The index of the file in `Editor::buffers`.
+    buffer: usize,
+    view: View,
+}
+
+/// An entry listed by a picker.
+struct PickerEntry {
+    /// The text matched against the input.
+    name: String,
+    description: String,
+    /// Shown at the right edge, like the keys bound to a command.
+    note: String,
+}
+
+/// A list to choose from while prompting, such as the command palette.
+struct Picker {
+    entries: Vec<PickerEntry>,
+    /// The indices of the entries matching the input, the best matches first.
+    matches: Vec<usize>,
+    selected: usize,
+}
+
+impl Picker {
+    fn new(entries: Vec<PickerEntry>) -> Self {
+        let mut picker = Self {
+            entries,
+            matches: Vec::new(),
+            selected: 0,
+        };
+        picker.filter("");
+        picker
+    }
+
+    fn filter(&mut self, query: &str) {
+        let names = self.entries.iter().map(|entry| entry.name.as_str());
+        self.matches = fuzzy::filter(query, names);
+        self.selected = 0;
+    }
+}
+
+/// Matches of the query being searched for, highlighted on the screen.
+struct SearchHighlight {
+    query: SearchQuery,
+    /// The position of the match the cursor is on.
+    current: Option<Position>,
+    /// The 1-based index of the current match.
+    index: usize,
+    /// The number of matches, unless they were not counted.
+    total: Option<usize>,
+}
+
+impl SearchHighlight {
+    fn label(&self) -> String {
+        match (&self.current, self.total) {
+            (Some(_), Some(total)) => format!("(match {} of {total}) ", self.index),
+            (None, Some(total)) if total > 0 => format!("({total} matches) "),
+            _ => String::new(),
         }
     }
 }
@@ -397,7 +1778,254 @@ impl StatusMessage {
     }
 }
 
+/// A line listing the keys of the main commands.
+fn help(keymap: &Keymap) -> String {
+    let commands = [
+        (Command::Find, "find"),
+        (Command::Save, "save"),
+        (Command::Undo, "undo"),
+        (Command::Quit, "quit"),
+    ];
+    let entries: Vec<String> = commands
+        .into_iter()
+        .filter_map(|(command, name)| {
+            let keys = keymap.keys_for(command);
+            Some(format!("{} = {name}", keymap::describe(keys.first()?)))
+        })
+        .collect();
+    format!("HELP: {}", entries.join(" | "))
+}
+
+/// Moves the terminal cursor to the start of the line of the area and blanks the line.
+fn clear_line(area: &Area, line: usize) {
+    let start = Position {
+        x: area.x,
+        y: area.y + line,
+    };
+    Terminal::cursor_position(&start);
+    print!("{}", " ".repeat(area.width));
+    Terminal::cursor_position(&start);
+}
+
+/// Returns the start of the text that fits in the screen columns, keeping whole graphemes.
+fn truncate_to_width(text: &str, width: usize) -> &str {
+    let mut columns = 0;
+    for (index, grapheme) in text.grapheme_indices(true) {
+        columns += grapheme.width();
+        if columns > width {
+            return &text[..index];
+        }
+    }
+    text
+}
+
+/// Pads the text with spaces to take up at least the screen columns.
+fn pad_to_width(text: &str, width: usize) -> String {
+    format!("{text}{}", " ".repeat(width.saturating_sub(text.width())))
+}
+
+/// Opens the file, lazily if it is at least as large as configured.
+fn open_document(file_name: &str, config: &Config) -> Result<Document, io::Error> {
+    let size = fs::metadata(file_name)?.len();
+    if config
+        .large_file()
+        .is_some_and(|threshold| size >= threshold)
+    {
+        Document::open_lazy(file_name)
+    } else {
+        Document::open(file_name)
+    }
+}
+
+/// Whether autosave should write the document.
+fn needs_autosave(document: &Document) -> bool {
+    document.is_dirty() && document.file_name.is_some()
+}
+
 fn die(err: &io::Error) {
     Terminal::clear_screen();
     panic!("{}", err);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clipboard::tests::RecordingClipboard, terminal::Size};
+
+    fn editor(text: &str) -> Editor {
+        editor_with_input(text, Vec::new())
+    }
+
+    /// An editor whose prompts read the events.
+    fn editor_with_input(text: &str, input: Vec<Event>) -> Editor {
+        let mut document = Document::default();
+        document.insert_text(&Position::default(), text);
+        let buffers = vec![Buffer {
+            document,
+            ..Buffer::default()
+        }];
+        let size = Size {
+            width: 80,
+            height: 22,
+        };
+        let terminal = Terminal::headless(size, input);
+        let config = Config {
+            clipboard: String::from("none"),
+            ..Config::default()
+        };
+        Editor::new(terminal, config, buffers, String::new())
+    }
+
+    #[test]
+    fn copy_and_cut_send_the_text_to_the_clipboard() {
+        let mut editor = editor("one\ntwo\nthree");
+        let clipboard = RecordingClipboard::default();
+        editor.clipboard = Some(Box::new(clipboard.clone()));
+
+        editor.copy();
+        editor.cursor_position = Position { x: 0, y: 1 };
+        editor.cut();
+
+        assert_eq!(*clipboard.copied.borrow(), ["one\n", "two\n"]);
+        assert_eq!(editor.register, "two\n");
+        assert_eq!(
+            editor
+                .document
+                .text(&Position::default(), &Position { x: 5, y: 1 }),
+            "one\nthree"
+        );
+    }
+
+    #[test]
+    fn copying_without_a_clipboard_still_fills_the_register() {
+        let mut editor = editor("one");
+        editor.copy();
+        assert_eq!(editor.register, "one");
+    }
+
+    #[test]
+    fn picker_lines_are_cut_and_padded_by_display_width() {
+        assert_eq!(truncate_to_width("日本語", 5), "日本");
+        assert_eq!(truncate_to_width("e\u{301}tat", 1), "e\u{301}");
+        assert_eq!(truncate_to_width("abc", 10), "abc");
+        assert_eq!(pad_to_width("日本", 6), "日本  ");
+        assert_eq!(pad_to_width("日本語", 4), "日本語");
+    }
+
+    #[test]
+    fn quit_from_the_palette_counts_down() {
+        let typed = "quit\n".chars().map(|c| Event::Key(Key::Char(c)));
+        let input = (0..4).flat_map(|_| typed.clone()).collect();
+        let mut editor = editor_with_input("unsaved", input);
+        for _ in 0..3 {
+            editor.process_key(Key::Ctrl('p'));
+            assert!(!editor.should_quit);
+        }
+        editor.process_key(Key::Ctrl('p'));
+        assert!(editor.should_quit);
+    }
+
+    #[test]
+    fn other_commands_reset_the_quit_countdown() {
+        let mut editor = editor("unsaved");
+        editor.process_key(Key::Ctrl('q'));
+        editor.process_key(Key::Ctrl('q'));
+        editor.process_key(Key::Left);
+        for _ in 0..3 {
+            editor.process_key(Key::Ctrl('q'));
+            assert!(!editor.should_quit);
+        }
+        editor.process_key(Key::Ctrl('q'));
+        assert!(editor.should_quit);
+    }
+
+    #[test]
+    fn quit_and_close_count_down_separately() {
+        let mut editor = editor("unsaved");
+        for _ in 0..3 {
+            editor.process_key(Key::Ctrl('q'));
+        }
+        editor.process_key(Key::Alt('w'));
+        assert!(editor.document.is_dirty());
+        editor.process_key(Key::Ctrl('q'));
+        assert!(!editor.should_quit);
+    }
+
+    #[test]
+    fn cursor_moves_by_wrapped_lines() {
+        let long = "a".repeat(200);
+        let mut editor = editor(&format!("{long}\nbbbbb"));
+        editor.soft_wrap = true;
+        assert_eq!(editor.line_starts(0), [0, 80, 160]);
+        editor.cursor_position = Position { x: 10, y: 0 };
+
+        editor.move_visual_line(false);
+        assert_eq!(editor.cursor_position.x, 90);
+        editor.move_visual_line(false);
+        assert_eq!(editor.cursor_position.x, 170);
+        editor.move_visual_line(false);
+        assert_eq!((editor.cursor_position.x, editor.cursor_position.y), (5, 1));
+        editor.move_visual_line(true);
+        assert_eq!(
+            (editor.cursor_position.x, editor.cursor_position.y),
+            (165, 0)
+        );
+        editor.move_visual_line(true);
+        editor.move_visual_line(true);
+        assert_eq!(editor.cursor_position.x, 5);
+        editor.move_visual_line(true);
+        assert_eq!(editor.cursor_position.x, 5);
+    }
+
+    #[test]
+    fn cursor_moves_to_the_line_after_a_full_row() {
+        let mut editor = editor(&"a".repeat(160));
+        editor.soft_wrap = true;
+        assert_eq!(editor.line_starts(0), [0, 80, 160]);
+        editor.cursor_position = Position { x: 100, y: 0 };
+        editor.move_visual_line(false);
+        assert_eq!(editor.cursor_position.x, 160);
+        assert_eq!(editor.cursor_line().0, 2);
+        // Then on to the line after the last row, and back.
+        editor.move_visual_line(false);
+        assert_eq!((editor.cursor_position.x, editor.cursor_position.y), (0, 1));
+        editor.move_visual_line(true);
+        assert_eq!(
+            (editor.cursor_position.x, editor.cursor_position.y),
+            (160, 0)
+        );
+        editor.move_visual_line(true);
+        assert_eq!(editor.cursor_position.x, 80);
+    }
+
+    #[test]
+    fn cursor_stays_on_a_short_wrapped_line() {
+        let mut editor = editor(&format!("{} {}", "a".repeat(10), "b".repeat(100)));
+        editor.soft_wrap = true;
+        assert_eq!(editor.line_starts(0), [0, 11, 91]);
+        editor.cursor_position = Position { x: 61, y: 0 };
+        editor.move_visual_line(true);
+        // Column 50 is past the end of the first line, which ends at the space.
+        assert_eq!(editor.cursor_position.x, 10);
+    }
+
+    #[test]
+    fn wrapped_scrolling_keeps_the_cursor_line_on_screen() {
+        let rows = vec!["a".repeat(200); 30].join("\n");
+        let mut editor = editor(&rows);
+        editor.soft_wrap = true;
+        assert_eq!(editor.text_height(), 22);
+        let mut scroll_to = |x, y| {
+            editor.cursor_position = Position { x, y };
+            editor.scroll_wrapped();
+            (editor.offset.y, editor.offset_line)
+        };
+
+        // Each row takes 3 lines, so the 35th line at the bottom puts the 14th at the top.
+        assert_eq!(scroll_to(90, 11), (4, 1));
+        assert_eq!(scroll_to(0, 5), (4, 1));
+        assert_eq!(scroll_to(170, 3), (3, 2));
+        assert_eq!(scroll_to(0, 0), (0, 0));
+        assert_eq!(scroll_to(170, 7), (0, 2));
+    }
+}
@@ -0,0 +1,161 @@
+use std::{io, str};
+
+/// How much of a file is looked at for null bytes to tell binary data from text.
+const BINARY_CHECK_LEN: usize = 8000;
+
+/// The character encoding of a file, which it is saved in again.
+#[derive(PartialEq, Copy, Clone, Default)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// UTF-8 starting with a byte order mark.
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    /// Detects the encoding of a file from its bytes, or only the first ones unless
+    /// `complete`. UTF-16 is only recognized by its byte order mark, and text that is
+    /// not valid UTF-8 is taken as Latin-1.
+    /// Returns `None` for binary data, which has null bytes outside of UTF-16.
+    pub fn detect(bytes: &[u8], complete: bool) -> Option<Self> {
+        for encoding in [Self::Utf8Bom, Self::Utf16Le, Self::Utf16Be] {
+            if bytes.starts_with(encoding.bom()) {
+                return Some(encoding);
+            }
+        }
+        if bytes[..bytes.len().min(BINARY_CHECK_LEN)].contains(&0) {
+            return None;
+        }
+        match str::from_utf8(bytes) {
+            Ok(_) => Some(Self::Utf8),
+            // A character may be cut off at the end of the first bytes, but not of the file.
+            Err(err) if err.error_len().is_none() && !complete => Some(Self::Utf8),
+            Err(_) => Some(Self::Latin1),
+        }
+    }
+
+    /// The bytes a file in this encoding starts with.
+    pub fn bom(self) -> &'static [u8] {
+        match self {
+            Self::Utf8 | Self::Latin1 => &[],
+            Self::Utf8Bom => &[0xef, 0xbb, 0xbf],
+            Self::Utf16Le => &[0xff, 0xfe],
+            Self::Utf16Be => &[0xfe, 0xff],
+        }
+    }
+
+    /// Decodes text without a byte order mark, replacing invalid sequences.
+    pub fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Self::Utf8 | Self::Utf8Bom => String::from_utf8_lossy(bytes).into_owned(),
+            Self::Utf16Le | Self::Utf16Be => {
+                let units = bytes.chunks(2).map(|pair| match (self, pair) {
+                    (Self::Utf16Le, &[low, high]) => u16::from_le_bytes([low, high]),
+                    (_, &[high, low]) => u16::from_be_bytes([high, low]),
+                    // A lone byte at the end of the file.
+                    _ => 0xfffd,
+                });
+                char::decode_utf16(units)
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect()
+            }
+            Self::Latin1 => bytes.iter().copied().map(char::from).collect(),
+        }
+    }
+
+    /// Encodes text without a byte order mark. Fails if a character has no
+    /// representation in the encoding.
+    pub fn encode(self, text: &str) -> Result<Vec<u8>, io::Error> {
+        match self {
+            Self::Utf8 | Self::Utf8Bom => Ok(text.as_bytes().to_vec()),
+            Self::Utf16Le => Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            Self::Utf16Be => Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+            Self::Latin1 => text
+                .chars()
+                .map(|c| {
+                    u8::try_from(c).map_err(|_| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("`{c}` cannot be saved in {}", self.name()),
+                        )
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Utf8 => "UTF-8",
+            Self::Utf8Bom => "UTF-8 BOM",
+            Self::Utf16Le => "UTF-16LE",
+            Self::Utf16Be => "UTF-16BE",
+            Self::Latin1 => "Latin-1",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latin1_ending_like_a_cut_off_character() {
+        assert!(Encoding::detect(b"caf\xe9", true) == Some(Encoding::Latin1));
+        assert!(Encoding::detect("caf\u{e9}".as_bytes(), true) == Some(Encoding::Utf8));
+        // Only the start of the file was read, so the character may go on after it.
+        assert!(Encoding::detect(b"caf\xc3", false) == Some(Encoding::Utf8));
+    }
+
+    #[test]
+    fn encode_and_decode_round_trip() {
+        let encodings = [
+            Encoding::Utf8,
+            Encoding::Utf8Bom,
+            Encoding::Utf16Le,
+            Encoding::Utf16Be,
+            Encoding::Latin1,
+        ];
+        for encoding in encodings {
+            for text in ["", "plain\n", "caf\u{e9} \u{ff}\r\n"] {
+                let bytes = encoding.encode(text).unwrap();
+                assert_eq!(encoding.decode(&bytes), text, "{}", encoding.name());
+            }
+            if encoding != Encoding::Latin1 {
+                let text = "\u{20ac} \u{1f600} e\u{301}";
+                let bytes = encoding.encode(text).unwrap();
+                assert_eq!(encoding.decode(&bytes), text, "{}", encoding.name());
+            }
+        }
+        assert_eq!(Encoding::Utf16Le.encode("a").unwrap(), b"a\0");
+        assert_eq!(Encoding::Utf16Be.encode("a").unwrap(), b"\0a");
+        assert_eq!(Encoding::Latin1.encode("\u{e9}").unwrap(), b"\xe9");
+    }
+
+    #[test]
+    fn every_latin1_byte_round_trips() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let text = Encoding::Latin1.decode(&bytes);
+        assert_eq!(Encoding::Latin1.encode(&text).unwrap(), bytes);
+    }
+
+    #[test]
+    fn latin1_cannot_encode_other_characters() {
+        let err = Encoding::Latin1.encode("a\u{20ac}").unwrap_err();
+        assert!(err.kind() == io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "`\u{20ac}` cannot be saved in Latin-1");
+    }
+
+    #[test]
+    fn detect_recognizes_byte_order_marks() {
+        for encoding in [Encoding::Utf8Bom, Encoding::Utf16Le, Encoding::Utf16Be] {
+            let mut bytes = encoding.bom().to_vec();
+            bytes.extend(encoding.encode("text\n").unwrap());
+            assert!(Encoding::detect(&bytes, true) == Some(encoding));
+        }
+        assert!(Encoding::detect(b"a\0b", true).is_none());
+    }
+}
@@ -0,0 +1,262 @@
+use crate::highlighting;
+use std::path::Path;
+
+pub struct FileType {
+    name: String,
+    hl_opts: HighlightingOptions,
+}
+
+/// A span that may continue over multiple rows, like a block comment.
+pub struct Block {
+    pub start: &'static str,
+    pub end: &'static str,
+    pub hl_type: highlighting::Type,
+}
+
+#[derive(Default)]
+pub struct HighlightingOptions {
+    numbers: bool,
+    /// Quotes that start and end a single-line string.
+    string_quotes: &'static [char],
+    characters: bool,
+    comment: Option<&'static str>,
+    blocks: Vec<Block>,
+    keywords: &'static [&'static str],
+    types: &'static [&'static str],
+    /// Rows starting with one of the characters are highlighted as a whole,
+    /// like headings in Markdown.
+    line_prefixes: &'static [(char, highlighting::Type)],
+}
+
+impl Default for FileType {
+    fn default() -> Self {
+        Self {
+            name: String::from("No filetype"),
+            hl_opts: HighlightingOptions::default(),
+        }
+    }
+}
+
+impl FileType {
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn highlighting_options(&self) -> &HighlightingOptions {
+        &self.hl_opts
+    }
+
+    pub fn from(file_name: &str) -> Self {
+        let extension = Path::new(file_name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+        match extension {
+            "rs" => Self::rust(),
+            "c" | "h" => Self::c(),
+            "py" => Self::python(),
+            "json" => Self::json(),
+            "toml" => Self::toml(),
+            "md" | "markdown" => Self::markdown(),
+            _ => Self::default(),
+        }
+    }
+
+    fn rust() -> Self {
+        Self {
+            name: String::from("Rust"),
+            hl_opts: HighlightingOptions {
+                numbers: true,
+                string_quotes: &['"'],
+                characters: true,
+                comment: Some("//"),
+                blocks: vec![Block {
+                    start: "/*",
+                    end: "*/",
+                    hl_type: highlighting::Type::Comment,
+                }],
+                keywords: &[
+                    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else",
+                    "enum", "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop",
+                    "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self",
+                    "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where",
+                    "while",
+                ],
+                types: &[
+                    "bool", "char", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32",
+                    "u64", "u128", "usize", "f32", "f64", "str", "String", "Vec", "Option",
+                    "Result", "Box", "Some", "None", "Ok", "Err",
+                ],
+                ..HighlightingOptions::default()
+            },
+        }
+    }
+
+    fn c() -> Self {
+        Self {
+            name: String::from("C"),
+            hl_opts: HighlightingOptions {
+                numbers: true,
+                string_quotes: &['"'],
+                characters: true,
+                comment: Some("//"),
+                blocks: vec![Block {
+                    start: "/*",
+                    end: "*/",
+                    hl_type: highlighting::Type::Comment,
+                }],
+                keywords: &[
+                    "auto", "break", "case", "const", "continue", "default", "do", "else", "enum",
+                    "extern", "for", "goto", "if", "inline", "register", "restrict", "return",
+                    "sizeof", "static", "struct", "switch", "typedef", "union", "volatile",
+                    "while", "NULL", "true", "false",
+                ],
+                types: &[
+                    "char", "double", "float", "int", "long", "short", "signed", "unsigned",
+                    "void", "bool", "size_t", "int8_t", "int16_t", "int32_t", "int64_t", "uint8_t",
+                    "uint16_t", "uint32_t", "uint64_t", "FILE",
+                ],
+                ..HighlightingOptions::default()
+            },
+        }
+    }
+
+    fn python() -> Self {
+        Self {
+            name: String::from("Python"),
+            hl_opts: HighlightingOptions {
+                numbers: true,
+                string_quotes: &['"', '\''],
+                comment: Some("#"),
+                blocks: vec![
+                    Block {
+                        start: "\"\"\"",
+                        end: "\"\"\"",
+                        hl_type: highlighting::Type::String,
+                    },
+                    Block {
+                        start: "'''",
+                        end: "'''",
+                        hl_type: highlighting::Type::String,
+                    },
+                ],
+                keywords: &[
+                    "and", "as", "assert", "async", "await", "break", "class", "continue", "def",
+                    "del", "elif", "else", "except", "False", "finally", "for", "from", "global",
+                    "if", "import", "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass",
+                    "raise", "return", "self", "True", "try", "while", "with", "yield",
+                ],
+                types: &[
+                    "bool",
+                    "bytes",
+                    "dict",
+                    "float",
+                    "frozenset",
+                    "int",
+                    "list",
+                    "object",
+                    "set",
+                    "str",
+                    "tuple",
+                    "type",
+                ],
+                ..HighlightingOptions::default()
+            },
+        }
+    }
+
+    fn json() -> Self {
+        Self {
+            name: String::from("JSON"),
+            hl_opts: HighlightingOptions {
+                numbers: true,
+                string_quotes: &['"'],
+                keywords: &["true", "false", "null"],
+                ..HighlightingOptions::default()
+            },
+        }
+    }
+
+    fn toml() -> Self {
+        Self {
+            name: String::from("TOML"),
+            hl_opts: HighlightingOptions {
+                numbers: true,
+                string_quotes: &['"', '\''],
+                comment: Some("#"),
+                blocks: vec![
+                    Block {
+                        start: "\"\"\"",
+                        end: "\"\"\"",
+                        hl_type: highlighting::Type::String,
+                    },
+                    Block {
+                        start: "'''",
+                        end: "'''",
+                        hl_type: highlighting::Type::String,
+                    },
+                ],
+                keywords: &["true", "false"],
+                line_prefixes: &[('[', highlighting::Type::Builtin)],
+                ..HighlightingOptions::default()
+            },
+        }
+    }
+
+    fn markdown() -> Self {
+        Self {
+            name: String::from("Markdown"),
+            hl_opts: HighlightingOptions {
+                string_quotes: &['`'],
+                blocks: vec![
+                    Block {
+                        start: "```",
+                        end: "```",
+                        hl_type: highlighting::Type::String,
+                    },
+                    Block {
+                        start: "<!--",
+                        end: "-->",
+                        hl_type: highlighting::Type::Comment,
+                    },
+                ],
+                line_prefixes: &[('#', highlighting::Type::Keyword)],
+                ..HighlightingOptions::default()
+            },
+        }
+    }
+}
+
+impl HighlightingOptions {
+    pub fn numbers(&self) -> bool {
+        self.numbers
+    }
+
+    pub fn string_quotes(&self) -> &[char] {
+        self.string_quotes
+    }
+
+    pub fn characters(&self) -> bool {
+        self.characters
+    }
+
+    pub fn comment(&self) -> Option<&str> {
+        self.comment
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    pub fn keywords(&self) -> &[&str] {
+        self.keywords
+    }
+
+    pub fn types(&self) -> &[&str] {
+        self.types
+    }
+
+    pub fn line_prefixes(&self) -> &[(char, highlighting::Type)] {
+        self.line_prefixes
+    }
+}
@@ -0,0 +1,88 @@
+/// Scores how well the query matches the text as a subsequence of its characters,
+/// ignoring case. Matches at the start of words and runs of consecutive characters
+/// score higher. Returns `None` if a character of the query is not found.
+pub fn score(query: &str, text: &str) -> Option<usize> {
+    let mut score = 0;
+    let mut chars = text.chars().enumerate();
+    let mut prev: Option<char> = None;
+    let mut last_match: Option<usize> = None;
+    for wanted in query.chars().flat_map(char::to_lowercase) {
+        loop {
+            let (i, c) = chars.next()?;
+            let at_word_start = prev.is_none_or(|p| !p.is_alphanumeric());
+            prev = Some(c);
+            if !c.to_lowercase().eq(wanted.to_lowercase()) {
+                continue;
+            }
+            score += 1;
+            if at_word_start {
+                score += 3;
+            }
+            if last_match.is_some_and(|last| last + 1 == i) {
+                score += 2;
+            }
+            last_match = Some(i);
+            break;
+        }
+    }
+    Some(score)
+}
+
+/// Returns the indices of the texts matching the query, the best matches first.
+pub fn filter<'a>(query: &str, texts: impl IntoIterator<Item = &'a str>) -> Vec<usize> {
+    let mut matches: Vec<(usize, usize)> = texts
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, text)| Some((score(query, text)?, i)))
+        .collect();
+    // The sort is stable, so equal scores keep the original order.
+    matches.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    matches.into_iter().map(|(_, i)| i).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn score_ignores_case() {
+        assert_eq!(score("SAVE", "save"), score("save", "Save"));
+        assert!(score("save", "SAVE").is_some());
+        assert_eq!(score("\u{c9}t\u{c9}", "\u{e9}t\u{e9}"), Some(10));
+    }
+
+    #[test]
+    fn score_needs_every_character_in_order() {
+        assert_eq!(score("", "anything"), Some(0));
+        assert!(score("qt", "quit").is_some());
+        assert!(score("tq", "quit").is_none());
+        assert!(score("quitt", "quit").is_none());
+    }
+
+    #[test]
+    fn word_starts_and_runs_score_higher() {
+        // 1 for a match, 3 more at the start of a word, 2 more right after the previous match.
+        assert_eq!(score("l", "line"), Some(4));
+        assert_eq!(score("i", "line"), Some(1));
+        assert_eq!(score("li", "line"), Some(7));
+        // Matching is greedy, so the `n` of `line` is taken.
+        assert_eq!(score("ln", "line-numbers"), Some(5));
+        assert_eq!(score("ln", "list numbers"), Some(8));
+        assert!(score("sb", "switch-buffer") > score("sb", "subtitle"));
+        assert!(score("line", "line-endings") > score("line", "outline"));
+    }
+
+    #[test]
+    fn filter_puts_the_best_matches_first_and_keeps_ties_in_order() {
+        let texts = [
+            "outline",
+            "line-endings",
+            "redo",
+            "line-numbers",
+            "soft-wrap",
+        ];
+        assert_eq!(filter("line", texts), [1, 3, 0]);
+        assert_eq!(filter("", texts), [0, 1, 2, 3, 4]);
+        assert!(filter("xyz", texts).is_empty());
+    }
+}
@@ -0,0 +1,47 @@
+use termion::color;
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Type {
+    None,
+    Number,
+    String,
+    Character,
+    Comment,
+    Keyword,
+    /// Built-in and well-known type names.
+    Builtin,
+}
+
+impl Type {
+    /// The foreground color of the type, or `None` to use the terminal default.
+    pub fn to_color(self) -> Option<color::Rgb> {
+        match self {
+            Type::None => None,
+            Type::Number => Some(color::Rgb(220, 163, 163)),
+            Type::String | Type::Character => Some(color::Rgb(211, 54, 130)),
+            Type::Comment => Some(color::Rgb(133, 153, 0)),
+            Type::Keyword => Some(color::Rgb(181, 137, 0)),
+            Type::Builtin => Some(color::Rgb(42, 161, 152)),
+        }
+    }
+}
+
+/// Emphasis drawn on top of the syntax highlighting.
+#[derive(PartialEq, Clone, Copy)]
+pub enum Overlay {
+    Match,
+    CurrentMatch,
+    /// Drawn with inverted colors.
+    Selection,
+}
+
+impl Overlay {
+    /// The background color of the overlay, or `None` if it inverts the colors instead.
+    pub fn to_bg_color(self) -> Option<color::Rgb> {
+        match self {
+            Overlay::Match => Some(color::Rgb(88, 88, 128)),
+            Overlay::CurrentMatch => Some(color::Rgb(203, 75, 22)),
+            Overlay::Selection => None,
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use crate::Position;
+
+/// A single primitive change made to a document.
+#[derive(Clone)]
+pub enum Edit {
+    /// A grapheme was inserted at the position, from the byte index `byte` in the row.
+    /// The byte index is kept because the text may combine with the grapheme before it.
+    Insert {
+        at: Position,
+        byte: usize,
+        text: String,
+    },
+    /// A grapheme was removed at the position, from the byte index `byte` in the row.
+    Delete {
+        at: Position,
+        byte: usize,
+        text: String,
+    },
+    /// The row was split into two at the position.
+    Split { at: Position },
+    /// The next row was joined at the end of the row.
+    Join { at: Position },
+    /// An empty row was added after the last row.
+    PushRow { at: Position },
+    /// A text spanning any number of rows was inserted at the position,
+    /// taking up the bytes from `start` to `end`.
+    InsertText {
+        at: Position,
+        start: Offset,
+        end: Offset,
+        text: String,
+    },
+    /// A text spanning any number of rows was removed at the position,
+    /// from the bytes between `start` and `end`.
+    DeleteText {
+        at: Position,
+        start: Offset,
+        end: Offset,
+        text: String,
+    },
+}
+
+/// A place in the rows given by a byte index in a row. Unlike a grapheme index, it does
+/// not move when an edit combines text with the graphemes next to it.
+#[derive(Clone, Copy)]
+pub struct Offset {
+    pub y: usize,
+    pub byte: usize,
+}
+
+impl Edit {
+    /// Whether this edit directly continues the `prev` one,
+    /// so that both can be undone at once.
+    fn continues(&self, prev: &Self) -> bool {
+        match (prev, self) {
+            (Self::Insert { at: a, .. }, Self::Insert { at: b, .. }) => {
+                a.y == b.y && a.x.saturating_add(1) == b.x
+            }
+            (Self::PushRow { at: a }, Self::Insert { at: b, .. }) => a.y == b.y && b.x == 0,
+            // Backspace moves to the left, Delete stays in place.
+            (Self::Delete { at: a, .. }, Self::Delete { at: b, .. }) => {
+                a.y == b.y && (a.x == b.x || a.x == b.x.saturating_add(1))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A group of edits that is undone and redone as a whole.
+struct Step {
+    id: usize,
+    edits: Vec<Edit>,
+}
+
+/// Undo/redo stacks of a document.
+pub struct History {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+    /// The last assigned step ID. `0` represents the initial state.
+    last_id: usize,
+    /// The state ID at the time the document was saved last,
+    /// or `None` if that state is no longer reachable.
+    saved_id: Option<usize>,
+    /// How many groups are open. While positive, edits are collected into
+    /// a single step regardless of their positions.
+    group_depth: usize,
+    /// The ID of the step the current group is collected into.
+    group_id: Option<usize>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            last_id: 0,
+            saved_id: Some(0),
+            group_depth: 0,
+            group_id: None,
+        }
+    }
+}
+
+impl History {
+    pub fn record(&mut self, edit: Edit) {
+        self.redo.clear();
+        if let Some(step) = self.undo.last_mut() {
+            let merge = if self.group_depth > 0 {
+                self.group_id == Some(step.id)
+            } else {
+                let continues = step.edits.last().is_some_and(|prev| edit.continues(prev));
+                // Never extend the saved state, or undo could not get back to it.
+                continues && self.saved_id != Some(step.id)
+            };
+            if merge {
+                step.edits.push(edit);
+                return;
+            }
+        }
+        self.last_id += 1;
+        self.undo.push(Step {
+            id: self.last_id,
+            edits: vec![edit],
+        });
+        if self.group_depth > 0 {
+            self.group_id = Some(self.last_id);
+        }
+    }
+
+    /// Starts collecting the following edits into one step until `end_group` is called.
+    /// Groups may be nested, and the outermost one makes the step.
+    pub fn begin_group(&mut self) {
+        if self.group_depth == 0 {
+            self.group_id = None;
+        }
+        self.group_depth += 1;
+    }
+
+    pub fn end_group(&mut self) {
+        self.group_depth = self.group_depth.saturating_sub(1);
+        if self.group_depth == 0 {
+            self.group_id = None;
+        }
+    }
+
+    /// Pops the last step and returns its edits in the order they should be reverted.
+    pub fn undo(&mut self) -> Option<Vec<Edit>> {
+        let step = self.undo.pop()?;
+        let edits = step.edits.iter().rev().cloned().collect();
+        self.redo.push(step);
+        Some(edits)
+    }
+
+    /// Pops the last undone step and returns its edits in the order they should be applied.
+    pub fn redo(&mut self) -> Option<Vec<Edit>> {
+        let step = self.redo.pop()?;
+        let edits = step.edits.clone();
+        self.undo.push(step);
+        Some(edits)
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved_id = Some(self.current_id());
+    }
+
+    /// Marks the document as modified without recording an undoable edit.
+    pub fn mark_unsaved(&mut self) {
+        self.saved_id = None;
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.saved_id != Some(self.current_id())
+    }
+
+    fn current_id(&self) -> usize {
+        self.undo.last().map_or(0, |step| step.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(x: usize, text: &str) -> Edit {
+        Edit::Insert {
+            at: Position { x, y: 0 },
+            byte: x,
+            text: text.to_string(),
+        }
+    }
+
+    fn texts(edits: &[Edit]) -> Vec<&str> {
+        edits
+            .iter()
+            .map(|edit| match edit {
+                Edit::Insert { text, .. } | Edit::Delete { text, .. } => text.as_str(),
+                _ => "",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn undo_and_redo_round_trip() {
+        let mut history = History::default();
+        assert!(history.undo().is_none());
+        history.record(insert(0, "a"));
+        history.record(insert(1, "b"));
+        // Not continuing the typing, so it becomes a step of its own.
+        history.record(insert(5, "c"));
+
+        assert_eq!(texts(&history.undo().unwrap()), ["c"]);
+        assert_eq!(texts(&history.undo().unwrap()), ["b", "a"]);
+        assert!(history.undo().is_none());
+        assert_eq!(texts(&history.redo().unwrap()), ["a", "b"]);
+        assert_eq!(texts(&history.redo().unwrap()), ["c"]);
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn a_new_edit_clears_redo() {
+        let mut history = History::default();
+        history.record(insert(0, "a"));
+        history.undo();
+        history.record(insert(0, "b"));
+        assert!(history.redo().is_none());
+        assert_eq!(texts(&history.undo().unwrap()), ["b"]);
+    }
+
+    #[test]
+    fn groups_are_undone_at_once() {
+        let mut history = History::default();
+        history.begin_group();
+        history.record(insert(0, "a"));
+        history.begin_group();
+        history.record(insert(7, "b"));
+        history.end_group();
+        history.record(insert(3, "c"));
+        history.end_group();
+        history.record(insert(9, "d"));
+
+        assert_eq!(texts(&history.undo().unwrap()), ["d"]);
+        assert_eq!(texts(&history.undo().unwrap()), ["c", "b", "a"]);
+    }
+
+    #[test]
+    fn modified_after_save_undo_and_redo() {
+        let mut history = History::default();
+        assert!(!history.is_modified());
+        history.record(insert(0, "a"));
+        assert!(history.is_modified());
+        history.mark_saved();
+        assert!(!history.is_modified());
+
+        // Typing on after saving starts a new step, so undo gets back to the saved state.
+        history.record(insert(1, "b"));
+        assert!(history.is_modified());
+        history.undo();
+        assert!(!history.is_modified());
+        history.undo();
+        assert!(history.is_modified());
+        history.redo();
+        assert!(!history.is_modified());
+        history.redo();
+        assert!(history.is_modified());
+        history.undo();
+        assert!(!history.is_modified());
+
+        history.mark_unsaved();
+        assert!(history.is_modified());
+        history.redo();
+        history.undo();
+        assert!(history.is_modified());
+    }
+
+    #[test]
+    fn saved_state_is_lost_when_redo_is_cleared() {
+        let mut history = History::default();
+        history.record(insert(0, "a"));
+        history.mark_saved();
+        history.undo();
+        history.record(insert(0, "b"));
+        history.undo();
+        assert!(history.is_modified());
+        assert!(history.redo().is_some());
+        assert!(history.is_modified());
+    }
+}
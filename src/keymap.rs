@@ -0,0 +1,275 @@
+use crate::command::{Command, COMMANDS};
+use std::collections::{BTreeMap, HashMap};
+use termion::event::Key;
+
+/// Maps key sequences, like `Ctrl-X Ctrl-S`, to commands.
+pub struct Keymap {
+    bindings: HashMap<Vec<Key>, Command>,
+}
+
+/// The result of looking up the keys pressed so far.
+pub enum Lookup {
+    Command(Command),
+    /// The keys start a longer sequence.
+    Prefix,
+    Unbound,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = COMMANDS
+            .iter()
+            .flat_map(|info| {
+                info.default_keys
+                    .iter()
+                    .map(|key| (vec![*key], info.command))
+            })
+            .collect();
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    /// Builds the default keymap, with the commands in `overrides` bound to their
+    /// configured key sequences instead of their default keys. A sequence that is
+    /// bound to another command, or that starts or is started by one, is an error.
+    pub fn new(overrides: &BTreeMap<String, String>) -> Result<Self, String> {
+        let mut keymap = Self::default();
+        let mut bindings = Vec::new();
+        for (name, spec) in overrides {
+            let command =
+                Command::from_name(name).ok_or_else(|| format!("Unknown command: {name}"))?;
+            keymap.bindings.retain(|_, bound| *bound != command);
+            if spec.trim().is_empty() {
+                continue;
+            }
+            let keys = parse_keys(spec).ok_or_else(|| format!("Unknown key: {spec}"))?;
+            bindings.push((keys, command));
+        }
+        for (keys, command) in bindings {
+            let conflict = keymap
+                .bindings
+                .iter()
+                .find(|(bound, _)| bound.starts_with(&keys) || keys.starts_with(bound));
+            if let Some((bound, other)) = conflict {
+                return Err(format!(
+                    "{} for {} conflicts with {} for {}",
+                    describe(&keys),
+                    command.name(),
+                    describe(bound),
+                    other.name()
+                ));
+            }
+            keymap.bindings.insert(keys, command);
+        }
+        Ok(keymap)
+    }
+
+    /// A sequence both bound and starting a longer one is treated as a prefix.
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        let is_prefix = self
+            .bindings
+            .keys()
+            .any(|bound| bound.len() > keys.len() && bound.starts_with(keys));
+        if is_prefix {
+            return Lookup::Prefix;
+        }
+        self.bindings
+            .get(keys)
+            .map_or(Lookup::Unbound, |command| Lookup::Command(*command))
+    }
+
+    /// Returns the key sequences bound to the command, shortest first.
+    pub fn keys_for(&self, command: Command) -> Vec<&[Key]> {
+        let mut keys: Vec<&[Key]> = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| **bound == command)
+            .map(|(keys, _)| keys.as_slice())
+            .collect();
+        keys.sort_by_key(|keys| (keys.len(), describe(keys)));
+        keys
+    }
+}
+
+/// Parses a key sequence written like `ctrl-x ctrl-s`.
+fn parse_keys(spec: &str) -> Option<Vec<Key>> {
+    spec.split_whitespace().map(parse_key).collect()
+}
+
+/// Parses a key written like `ctrl-s`, `alt-x`, `f5`, `pageup` or `a`.
+fn parse_key(spec: &str) -> Option<Key> {
+    let single = |name: &str| {
+        let mut chars = name.chars();
+        let c = chars.next()?;
+        chars.next().is_none().then_some(c)
+    };
+    if let Some(name) = spec.strip_prefix("ctrl-") {
+        if name == "space" {
+            return Some(Key::Null);
+        }
+        return single(name).map(|c| Key::Ctrl(c.to_ascii_lowercase()));
+    }
+    if let Some(name) = spec.strip_prefix("alt-") {
+        return single(name).map(Key::Alt);
+    }
+    let key = match spec {
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "backspace" => Key::Backspace,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "esc" => Key::Esc,
+        "tab" => Key::Char('\t'),
+        "backtab" => Key::BackTab,
+        "enter" => Key::Char('\n'),
+        "space" => Key::Char(' '),
+        _ => {
+            let function = spec
+                .strip_prefix('f')
+                .and_then(|n| n.parse().ok())
+                .filter(|n| (1..=12).contains(n));
+            return function.map(Key::F).or_else(|| single(spec).map(Key::Char));
+        }
+    };
+    Some(key)
+}
+
+/// Describes a key sequence the way it is written in the help, like `Ctrl-X Ctrl-S`.
+pub fn describe(keys: &[Key]) -> String {
+    let names: Vec<String> = keys
+        .iter()
+        .map(|key| match key {
+            Key::Ctrl(c) => format!("Ctrl-{}", c.to_ascii_uppercase()),
+            Key::Alt(c) => format!("Alt-{c}"),
+            Key::Char('\t') => String::from("Tab"),
+            Key::Char('\n') => String::from("Enter"),
+            Key::Char(' ') => String::from("Space"),
+            Key::Char(c) => c.to_string(),
+            Key::F(n) => format!("F{n}"),
+            Key::Null => String::from("Ctrl-Space"),
+            Key::BackTab => String::from("Shift-Tab"),
+            Key::PageUp => String::from("PageUp"),
+            Key::PageDown => String::from("PageDown"),
+            key => format!("{key:?}"),
+        })
+        .collect();
+    names.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_single_keys() {
+        assert_eq!(parse_key("ctrl-s"), Some(Key::Ctrl('s')));
+        assert_eq!(parse_key("ctrl-S"), Some(Key::Ctrl('s')));
+        assert_eq!(parse_key("ctrl-space"), Some(Key::Null));
+        assert_eq!(parse_key("alt-x"), Some(Key::Alt('x')));
+        assert_eq!(parse_key("pageup"), Some(Key::PageUp));
+        assert_eq!(parse_key("backtab"), Some(Key::BackTab));
+        assert_eq!(parse_key("tab"), Some(Key::Char('\t')));
+        assert_eq!(parse_key("enter"), Some(Key::Char('\n')));
+        assert_eq!(parse_key("space"), Some(Key::Char(' ')));
+        assert_eq!(parse_key("a"), Some(Key::Char('a')));
+        assert_eq!(parse_key("\u{e9}"), Some(Key::Char('\u{e9}')));
+        assert_eq!(parse_key("f"), Some(Key::Char('f')));
+        assert_eq!(parse_key("f5"), Some(Key::F(5)));
+        assert_eq!(parse_key("f12"), Some(Key::F(12)));
+    }
+
+    #[test]
+    fn parse_rejects_unknown_keys() {
+        for spec in [
+            "", "f0", "f13", "fx", "ctrl-", "ctrl-xy", "alt-", "shift-a", "Up",
+        ] {
+            assert_eq!(parse_key(spec), None, "{spec}");
+        }
+    }
+
+    #[test]
+    fn parse_key_sequences() {
+        assert_eq!(
+            parse_keys("ctrl-x  ctrl-s"),
+            Some(vec![Key::Ctrl('x'), Key::Ctrl('s')])
+        );
+        assert_eq!(parse_keys(" f5 "), Some(vec![Key::F(5)]));
+        assert_eq!(parse_keys("ctrl-x bogus"), None);
+        assert_eq!(
+            describe(&parse_keys("ctrl-x ctrl-space tab").unwrap()),
+            "Ctrl-X Ctrl-Space Tab"
+        );
+    }
+
+    #[test]
+    fn overrides_replace_default_keys() {
+        let overrides = BTreeMap::from([
+            (String::from("save"), String::from("ctrl-x ctrl-s")),
+            (String::from("cut"), String::from("alt-x")),
+        ]);
+        let keymap = Keymap::new(&overrides).unwrap();
+        assert!(matches!(keymap.lookup(&[Key::Ctrl('s')]), Lookup::Unbound));
+        assert!(matches!(keymap.lookup(&[Key::Ctrl('x')]), Lookup::Prefix));
+        assert!(matches!(
+            keymap.lookup(&[Key::Ctrl('x'), Key::Ctrl('s')]),
+            Lookup::Command(Command::Save)
+        ));
+        assert!(matches!(
+            keymap.lookup(&[Key::Alt('x')]),
+            Lookup::Command(Command::Cut)
+        ));
+
+        let unbound = BTreeMap::from([(String::from("save"), String::new())]);
+        assert!(Keymap::new(&unbound)
+            .unwrap()
+            .keys_for(Command::Save)
+            .is_empty());
+    }
+
+    #[test]
+    fn overrides_must_not_take_keys_of_other_commands() {
+        let error = |overrides: &[(&str, &str)]| {
+            let overrides = overrides
+                .iter()
+                .map(|(name, spec)| (name.to_string(), spec.to_string()))
+                .collect();
+            Keymap::new(&overrides).err().unwrap()
+        };
+        assert_eq!(
+            error(&[("save", "ctrl-x ctrl-s")]),
+            "Ctrl-X Ctrl-S for save conflicts with Ctrl-X for cut"
+        );
+        assert_eq!(
+            error(&[("copy", "ctrl-s")]),
+            "Ctrl-S for copy conflicts with Ctrl-S for save"
+        );
+        assert_eq!(
+            error(&[("copy", "alt-x"), ("cut", "alt-x alt-y")]),
+            "Alt-x Alt-y for cut conflicts with Alt-x for copy"
+        );
+        // Keys given up by a command can be taken by another one.
+        let swapped = [("copy", "ctrl-x"), ("cut", "ctrl-c")]
+            .iter()
+            .map(|(name, spec)| (name.to_string(), spec.to_string()))
+            .collect();
+        assert!(Keymap::new(&swapped).is_ok());
+    }
+
+    #[test]
+    fn overrides_report_errors() {
+        let unknown = BTreeMap::from([(String::from("sav"), String::from("ctrl-s"))]);
+        assert_eq!(Keymap::new(&unknown).err().unwrap(), "Unknown command: sav");
+        let bad_key = BTreeMap::from([(String::from("save"), String::from("ctrl-x hyper-s"))]);
+        assert_eq!(
+            Keymap::new(&bad_key).err().unwrap(),
+            "Unknown key: ctrl-x hyper-s"
+        );
+    }
+}
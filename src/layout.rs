@@ -0,0 +1,273 @@
+use std::mem;
+
+/// The narrowest a pane can get by splitting it.
+const MIN_PANE_WIDTH: usize = 10;
+/// The lowest a pane can get by splitting it, counting its status line.
+const MIN_PANE_HEIGHT: usize = 2;
+
+/// How a split arranges its two parts.
+#[derive(PartialEq, Clone, Copy)]
+pub enum Direction {
+    /// One above the other.
+    Horizontal,
+    /// Side by side, with a separator column between them.
+    Vertical,
+}
+
+/// A rectangle of the screen, in columns and lines.
+#[derive(PartialEq, Clone, Copy)]
+pub struct Area {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Area {
+    /// Whether both parts of a split would still be usable.
+    pub fn can_split(&self, direction: Direction) -> bool {
+        match direction {
+            Direction::Horizontal => self.height >= MIN_PANE_HEIGHT * 2,
+            Direction::Vertical => self.width > MIN_PANE_WIDTH * 2,
+        }
+    }
+
+    /// Returns the two parts of the area and the separator between them, if any.
+    fn split(self, direction: Direction) -> (Self, Self, Option<Self>) {
+        match direction {
+            Direction::Horizontal => {
+                let top = self.height.div_ceil(2);
+                let first = Self {
+                    height: top,
+                    ..self
+                };
+                let second = Self {
+                    y: self.y + top,
+                    height: self.height - top,
+                    ..self
+                };
+                (first, second, None)
+            }
+            Direction::Vertical => {
+                let left = self.width.saturating_sub(1) / 2;
+                let first = Self {
+                    width: left,
+                    ..self
+                };
+                let separator = Self {
+                    x: self.x + left,
+                    width: self.width.min(1),
+                    ..self
+                };
+                let second = Self {
+                    x: separator.x + separator.width,
+                    width: self.width - left - separator.width,
+                    ..self
+                };
+                (first, second, Some(separator))
+            }
+        }
+    }
+}
+
+/// How the screen is divided between panes, identified by their index.
+#[derive(PartialEq)]
+pub enum Layout {
+    Pane(usize),
+    Split {
+        direction: Direction,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Layout {
+    /// Returns the area of each pane in screen order, and the separators between them.
+    pub fn areas(&self, area: Area) -> (Vec<(usize, Area)>, Vec<Area>) {
+        let mut panes = Vec::new();
+        let mut separators = Vec::new();
+        self.collect_areas(area, &mut panes, &mut separators);
+        (panes, separators)
+    }
+
+    fn collect_areas(
+        &self,
+        area: Area,
+        panes: &mut Vec<(usize, Area)>,
+        separators: &mut Vec<Area>,
+    ) {
+        match self {
+            Self::Pane(pane) => panes.push((*pane, area)),
+            Self::Split {
+                direction,
+                first,
+                second,
+            } => {
+                let (first_area, second_area, separator) = area.split(*direction);
+                first.collect_areas(first_area, panes, separators);
+                separators.extend(separator);
+                second.collect_areas(second_area, panes, separators);
+            }
+        }
+    }
+
+    /// Returns the area of the pane, if it is part of the layout.
+    pub fn area_of(&self, pane: usize, area: Area) -> Option<Area> {
+        match self {
+            Self::Pane(index) => (*index == pane).then_some(area),
+            Self::Split {
+                direction,
+                first,
+                second,
+            } => {
+                let (first_area, second_area, _) = area.split(*direction);
+                first
+                    .area_of(pane, first_area)
+                    .or_else(|| second.area_of(pane, second_area))
+            }
+        }
+    }
+
+    /// Divides the place of the pane between it and the new pane.
+    pub fn split(&mut self, pane: usize, new_pane: usize, direction: Direction) {
+        match self {
+            Self::Pane(index) if *index == pane => {
+                *self = Self::Split {
+                    direction,
+                    first: Box::new(Self::Pane(pane)),
+                    second: Box::new(Self::Pane(new_pane)),
+                };
+            }
+            Self::Pane(_) => {}
+            Self::Split { first, second, .. } => {
+                first.split(pane, new_pane, direction);
+                second.split(pane, new_pane, direction);
+            }
+        }
+    }
+
+    /// Removes the pane, giving its place to the other part of its split, and
+    /// renumbers the panes after it. Returns the first pane of the part that took its place.
+    pub fn remove(&mut self, pane: usize) -> Option<usize> {
+        let sibling = self.remove_pane(pane)?;
+        self.renumber(pane);
+        Some(if sibling > pane { sibling - 1 } else { sibling })
+    }
+
+    fn remove_pane(&mut self, pane: usize) -> Option<usize> {
+        let Self::Split { first, second, .. } = self else {
+            return None;
+        };
+        let sibling = if **first == Self::Pane(pane) {
+            second
+        } else if **second == Self::Pane(pane) {
+            first
+        } else {
+            return first.remove_pane(pane).or_else(|| second.remove_pane(pane));
+        };
+        let sibling = mem::replace(sibling.as_mut(), Self::Pane(pane));
+        *self = sibling;
+        Some(self.first_pane())
+    }
+
+    fn renumber(&mut self, removed: usize) {
+        match self {
+            Self::Pane(index) if *index > removed => *index -= 1,
+            Self::Pane(_) => {}
+            Self::Split { first, second, .. } => {
+                first.renumber(removed);
+                second.renumber(removed);
+            }
+        }
+    }
+
+    fn first_pane(&self) -> usize {
+        match self {
+            Self::Pane(index) => *index,
+            Self::Split { first, .. } => first.first_pane(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCREEN: Area = area(0, 0, 41, 20);
+
+    const fn area(x: usize, y: usize, width: usize, height: usize) -> Area {
+        Area {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn panes(layout: &Layout) -> Vec<usize> {
+        layout
+            .areas(SCREEN)
+            .0
+            .into_iter()
+            .map(|(pane, _)| pane)
+            .collect()
+    }
+
+    /// Panes 0 and 2 side by side above 1, with 2 split into 2 and 3.
+    fn grid() -> Layout {
+        let mut layout = Layout::Pane(0);
+        layout.split(0, 1, Direction::Horizontal);
+        layout.split(0, 2, Direction::Vertical);
+        layout.split(2, 3, Direction::Horizontal);
+        layout
+    }
+
+    #[test]
+    fn splits_divide_the_area() {
+        let layout = grid();
+        let (areas, separators) = layout.areas(SCREEN);
+        assert_eq!(panes(&layout), [0, 2, 3, 1]);
+        let at = |pane| areas.iter().find(|(index, _)| *index == pane).unwrap().1;
+        assert!(at(0) == area(0, 0, 20, 10));
+        assert!(at(2) == area(21, 0, 20, 5));
+        assert!(at(3) == area(21, 5, 20, 5));
+        assert!(at(1) == area(0, 10, 41, 10));
+        assert!(separators == [area(20, 0, 1, 10)]);
+        assert!(layout.area_of(3, SCREEN) == Some(at(3)));
+        assert!(layout.area_of(4, SCREEN).is_none());
+    }
+
+    #[test]
+    fn removing_a_pane_gives_its_place_to_the_sibling_and_renumbers() {
+        let mut layout = grid();
+        assert_eq!(layout.remove(2), Some(2));
+        // Pane 3 took the place of 2 and became 2 itself.
+        assert_eq!(panes(&layout), [0, 2, 1]);
+        assert!(layout.area_of(2, SCREEN) == Some(area(21, 0, 20, 10)));
+
+        assert_eq!(layout.remove(1), Some(0));
+        assert_eq!(panes(&layout), [0, 1]);
+        assert!(layout.area_of(0, SCREEN) == Some(area(0, 0, 20, 20)));
+
+        assert_eq!(layout.remove(0), Some(0));
+        assert!(layout == Layout::Pane(0));
+        assert_eq!(layout.remove(0), None);
+        assert!(layout == Layout::Pane(0));
+    }
+
+    #[test]
+    fn removing_a_pane_returns_the_first_pane_of_a_split_sibling() {
+        let mut layout = grid();
+        assert_eq!(layout.remove(0), Some(1));
+        assert_eq!(panes(&layout), [1, 2, 0]);
+        assert_eq!(layout.remove(5), None);
+    }
+
+    #[test]
+    fn small_areas_cannot_be_split() {
+        assert!(!area(0, 0, 20, 3).can_split(Direction::Vertical));
+        assert!(!area(0, 0, 20, 3).can_split(Direction::Horizontal));
+        assert!(area(0, 0, 21, 4).can_split(Direction::Vertical));
+        assert!(area(0, 0, 21, 4).can_split(Direction::Horizontal));
+    }
+}
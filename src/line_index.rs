@@ -0,0 +1,172 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    iter,
+    ops::Range,
+    str,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+/// How much of the file is read at once, and how often the lines found are reported.
+const BLOCK_SIZE: usize = 1 << 20;
+
+/// What the indexing thread found in the file.
+pub enum IndexEvent {
+    /// The byte ranges of more lines, without their line breaks, and how far the file was read.
+    Lines {
+        lines: Vec<Range<u64>>,
+        scanned: u64,
+    },
+    /// The file was checked for UTF-8 and turned out not to be.
+    NotUtf8,
+    /// The whole file was read.
+    Done {
+        final_newline: bool,
+    },
+    Failed(io::Error),
+}
+
+/// Finds the lines of a file from the byte offset on in a background thread,
+/// reporting them as they are found. If `check_utf8`, the file is also checked to be UTF-8.
+pub fn spawn(file: File, start: u64, check_utf8: bool) -> Receiver<IndexEvent> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let utf8 = check_utf8.then(Utf8Check::default);
+        if let Err(err) = index(file, start, utf8, &sender) {
+            let _ = sender.send(IndexEvent::Failed(err));
+        }
+    });
+    receiver
+}
+
+fn index(
+    mut file: File,
+    mut start: u64,
+    mut utf8: Option<Utf8Check>,
+    sender: &Sender<IndexEvent>,
+) -> Result<(), io::Error> {
+    file.seek(SeekFrom::Start(start))?;
+    let mut buffer = vec![0; BLOCK_SIZE];
+    let mut scanned = start;
+    let mut last_byte = None;
+    // Invalid UTF-8 is reported once some lines were, so that the first one can be
+    // read again in another encoding.
+    let mut not_utf8 = false;
+    let mut found_lines = false;
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        if utf8
+            .as_mut()
+            .is_some_and(|utf8| !utf8.feed(&buffer[..read]))
+        {
+            utf8 = None;
+            not_utf8 = true;
+        }
+        let mut lines = Vec::new();
+        for (i, &byte) in buffer[..read].iter().enumerate() {
+            if byte != b'\n' {
+                continue;
+            }
+            let end = scanned + i as u64;
+            let before = if i > 0 {
+                Some(buffer[i - 1])
+            } else {
+                last_byte
+            };
+            // A CRLF line break is left out of the line like a LF one.
+            let line_end = if before == Some(b'\r') { end - 1 } else { end };
+            lines.push(start..line_end.max(start));
+            start = end + 1;
+        }
+        scanned += read as u64;
+        last_byte = Some(buffer[read - 1]);
+        found_lines |= !lines.is_empty();
+        let event = IndexEvent::Lines { lines, scanned };
+        if sender.send(event).is_err() {
+            // The document was closed.
+            return Ok(());
+        }
+        if not_utf8 && found_lines {
+            not_utf8 = false;
+            let _ = sender.send(IndexEvent::NotUtf8);
+        }
+    }
+    if start < scanned {
+        // The last line has no line break.
+        let lines = iter::once(start..scanned).collect();
+        let _ = sender.send(IndexEvent::Lines { lines, scanned });
+    }
+    if not_utf8 || utf8.is_some_and(|utf8| !utf8.is_complete()) {
+        let _ = sender.send(IndexEvent::NotUtf8);
+    }
+    // An empty file does not tell, so text typed into it gets a final line break.
+    let final_newline = matches!(last_byte, None | Some(b'\n'));
+    let _ = sender.send(IndexEvent::Done { final_newline });
+    Ok(())
+}
+
+/// Checks that blocks of bytes read one after the other are valid UTF-8.
+#[derive(Default)]
+struct Utf8Check {
+    /// The start of a character cut off at the end of the last block.
+    partial: Vec<u8>,
+}
+
+impl Utf8Check {
+    /// Checks the next block and returns whether it is valid so far.
+    fn feed(&mut self, mut bytes: &[u8]) -> bool {
+        // Complete the character cut off at the end of the last block first.
+        while !self.partial.is_empty() {
+            let Some((&byte, rest)) = bytes.split_first() else {
+                return true;
+            };
+            self.partial.push(byte);
+            bytes = rest;
+            match str::from_utf8(&self.partial) {
+                Ok(_) => self.partial.clear(),
+                Err(err) if err.error_len().is_none() => {}
+                Err(_) => return false,
+            }
+        }
+        match str::from_utf8(bytes) {
+            Ok(_) => true,
+            Err(err) if err.error_len().is_none() => {
+                self.partial = bytes[err.valid_up_to()..].to_vec();
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Whether the file did not end in the middle of a character.
+    fn is_complete(&self) -> bool {
+        self.partial.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf8_check_across_blocks() {
+        let text = "aé€😀".as_bytes();
+        for split in 0..=text.len() {
+            let mut utf8 = Utf8Check::default();
+            assert!(utf8.feed(&text[..split]), "{split}");
+            assert!(utf8.feed(&text[split..]), "{split}");
+            assert!(utf8.is_complete(), "{split}");
+        }
+        let mut utf8 = Utf8Check::default();
+        assert!(utf8.feed(b"caf\xc3"));
+        assert!(!utf8.is_complete());
+        assert!(!utf8.feed(b"x"));
+        assert!(!Utf8Check::default().feed(b"caf\xe9 ok"));
+    }
+}
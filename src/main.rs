@@ -1,23 +1,28 @@
 #![warn(clippy::all, clippy::pedantic)]
 
+mod atomic_write;
+mod clipboard;
+mod command;
+mod config;
 mod document;
 mod editor;
+mod encoding;
+mod filetype;
+mod fuzzy;
+mod highlighting;
+mod history;
+mod keymap;
+mod layout;
+mod line_index;
+mod position;
+mod rope;
 mod row;
+mod search;
 mod terminal;
 
-#[derive(Default, Clone)]
-pub struct Position {
-    x: usize,
-    y: usize,
-}
-
-#[derive(PartialEq, Copy, Clone)]
-pub enum SearchDirection {
-    Forward,
-    Backward,
-}
-
 use crate::editor::Editor;
+use position::Position;
+use search::SearchDirection;
 
 fn main() {
     Editor::default().run();
@@ -0,0 +1,6 @@
+/// A place in a document, given by the index of a grapheme in a row.
+#[derive(Default, Clone)]
+pub struct Position {
+    pub x: usize,
+    pub y: usize,
+}
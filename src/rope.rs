@@ -0,0 +1,401 @@
+use crate::{encoding::Encoding, row::Row};
+use std::{borrow::Cow, cell::OnceCell, fs::File, ops::Range, os::unix::fs::FileExt};
+
+/// The most rows kept in one chunk. Larger chunks are split in half.
+const CHUNK_SIZE: usize = 1024;
+
+/// The rows of a document, stored in chunks so that inserting or removing a row
+/// moves the rows of one chunk instead of every row after it.
+///
+/// The rows of a large file can be left in the file and read when they are first used.
+#[derive(Default)]
+pub struct Rope {
+    chunks: Vec<Vec<Line>>,
+    /// The index of the first row of each chunk.
+    starts: Vec<usize>,
+    len: usize,
+    /// The file unloaded lines are read from.
+    source: Option<File>,
+    /// The encoding of the lines in the file.
+    encoding: Encoding,
+}
+
+enum Line {
+    Loaded(Box<Row>),
+    /// A line of the source file not changed yet, given by its bytes without the line break.
+    /// The row is read from the file when it is shown.
+    Unloaded {
+        span: Range<u64>,
+        row: OnceCell<Box<Row>>,
+    },
+}
+
+impl Rope {
+    /// Creates an empty rope whose rows are added with `extend_unloaded` and read from the file.
+    pub fn lazy(source: File, encoding: Encoding) -> Self {
+        Self {
+            source: Some(source),
+            encoding,
+            ..Self::default()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether rows are read from a file as they are used.
+    pub fn is_lazy(&self) -> bool {
+        self.source.is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the row, reading it from the file and keeping it if it was not loaded yet.
+    pub fn get(&self, index: usize) -> Option<&Row> {
+        let line = self.line(index)?;
+        Some(match line {
+            Line::Loaded(row) => row,
+            Line::Unloaded { span, row } => row.get_or_init(|| Box::new(self.read(span))),
+        })
+    }
+
+    /// Returns the row, reading it from the file without keeping it if it was not loaded yet.
+    /// This saves memory when going over every row.
+    pub fn peek(&self, index: usize) -> Option<Cow<'_, Row>> {
+        let line = self.line(index)?;
+        Some(match line {
+            Line::Loaded(row) => Cow::Borrowed(row),
+            Line::Unloaded { span, row } => match row.get() {
+                Some(row) => Cow::Borrowed(row),
+                None => Cow::Owned(self.read(span)),
+            },
+        })
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Row> {
+        if index >= self.len {
+            return None;
+        }
+        let (chunk, offset) = self.locate(index);
+        if let Line::Unloaded { span, row } = &mut self.chunks[chunk][offset] {
+            let span = span.clone();
+            let row = row.take();
+            let row = row.unwrap_or_else(|| Box::new(self.read(&span)));
+            self.chunks[chunk][offset] = Line::Loaded(row);
+        }
+        match &mut self.chunks[chunk][offset] {
+            Line::Loaded(row) => Some(row),
+            Line::Unloaded { .. } => None,
+        }
+    }
+
+    /// Returns the row to update how it is drawn, keeping an unloaded line unchanged
+    /// so that it is still saved as it is in the file.
+    pub fn get_shown_mut(&mut self, index: usize) -> Option<&mut Row> {
+        self.get(index)?;
+        let (chunk, offset) = self.locate(index);
+        match &mut self.chunks[chunk][offset] {
+            Line::Loaded(row) => Some(row),
+            Line::Unloaded { row, .. } => row.get_mut().map(AsMut::as_mut),
+        }
+    }
+
+    pub fn last(&self) -> Option<&Row> {
+        self.get(self.len.checked_sub(1)?)
+    }
+
+    /// Changes the encoding unchanged lines are read in. `bom` bytes at the start of the
+    /// file, left out of the first line so far, become part of it as text.
+    pub fn set_encoding(&mut self, encoding: Encoding, bom: usize) {
+        self.encoding = encoding;
+        let bom_text = encoding.decode(&self.read_bytes(&(0..bom as u64)));
+        for (i, line) in self.chunks.iter_mut().flatten().enumerate() {
+            match line {
+                Line::Unloaded { span, row } => {
+                    if i == 0 {
+                        span.start = 0;
+                    }
+                    // Read it again in the new encoding when it is used.
+                    row.take();
+                }
+                Line::Loaded(row) if i == 0 => row.insert_str(0, &bom_text),
+                Line::Loaded(_) => {}
+            }
+        }
+    }
+
+    /// Returns the bytes of a line not changed since it was read from the file,
+    /// so that it can be saved as it is.
+    pub fn unchanged_bytes(&self, index: usize) -> Option<Vec<u8>> {
+        match self.line(index)? {
+            Line::Loaded(_) => None,
+            Line::Unloaded { span, .. } => Some(self.read_bytes(span)),
+        }
+    }
+
+    pub fn push(&mut self, row: Row) {
+        self.splice(self.len, vec![row]);
+    }
+
+    pub fn pop(&mut self) -> Option<Row> {
+        let last = self.len.checked_sub(1)?;
+        Some(self.remove(last))
+    }
+
+    pub fn insert(&mut self, index: usize, row: Row) {
+        self.splice(index, vec![row]);
+    }
+
+    /// Inserts the rows before the row at the index, or at the end.
+    pub fn splice(&mut self, index: usize, rows: Vec<Row>) {
+        let lines = rows
+            .into_iter()
+            .map(|row| Line::Loaded(Box::new(row)))
+            .collect();
+        self.splice_lines(index, lines);
+    }
+
+    /// Adds lines of the source file at the end, to be read when they are used.
+    pub fn extend_unloaded(&mut self, spans: Vec<Range<u64>>) {
+        let lines = spans
+            .into_iter()
+            .map(|span| Line::Unloaded {
+                span,
+                row: OnceCell::new(),
+            })
+            .collect();
+        self.splice_lines(self.len, lines);
+    }
+
+    fn splice_lines(&mut self, index: usize, lines: Vec<Line>) {
+        if lines.is_empty() {
+            return;
+        }
+        if self.chunks.is_empty() {
+            self.chunks.push(Vec::new());
+            self.starts.push(0);
+        }
+        let (chunk, offset) = self.locate(index.min(self.len));
+        self.len += lines.len();
+        self.chunks[chunk].splice(offset..offset, lines);
+        if self.chunks[chunk].len() > CHUNK_SIZE {
+            let tail = self.chunks[chunk].split_off(0);
+            let pieces = tail.len().div_ceil(CHUNK_SIZE / 2);
+            let mut lines = tail.into_iter();
+            let new_chunks: Vec<Vec<Line>> = (0..pieces)
+                .map(|_| lines.by_ref().take(CHUNK_SIZE / 2).collect())
+                .collect();
+            self.chunks.splice(chunk..=chunk, new_chunks);
+        }
+        self.update_starts();
+    }
+
+    /// Panics if the index is out of bounds, like `Vec::remove`.
+    pub fn remove(&mut self, index: usize) -> Row {
+        assert!(index < self.len, "row index {index} out of bounds");
+        let (chunk, offset) = self.locate(index);
+        let line = self.chunks[chunk].remove(offset);
+        self.len -= 1;
+        if self.chunks[chunk].is_empty() {
+            self.chunks.remove(chunk);
+        }
+        self.update_starts();
+        match line {
+            Line::Loaded(row) => *row,
+            Line::Unloaded { span, row } => row
+                .into_inner()
+                .map_or_else(|| self.read(&span), |row| *row),
+        }
+    }
+
+    /// Removes the rows in the range.
+    pub fn drain(&mut self, range: Range<usize>) {
+        let end = range.end.min(self.len);
+        if range.start >= end {
+            return;
+        }
+        let (mut chunk, offset) = self.locate(range.start);
+        let mut remaining = end - range.start;
+        self.len -= remaining;
+        // Only the first chunk is entered in the middle, the rest are removed from their start.
+        let mut offset = offset;
+        while remaining > 0 {
+            let count = remaining.min(self.chunks[chunk].len() - offset);
+            self.chunks[chunk].drain(offset..offset + count);
+            remaining -= count;
+            if self.chunks[chunk].is_empty() {
+                self.chunks.remove(chunk);
+            } else {
+                chunk += 1;
+            }
+            offset = 0;
+        }
+        self.update_starts();
+    }
+
+    fn line(&self, index: usize) -> Option<&Line> {
+        if index >= self.len {
+            return None;
+        }
+        let (chunk, offset) = self.locate(index);
+        self.chunks[chunk].get(offset)
+    }
+
+    /// Returns the chunk holding the row at the index, and the index of the row in it.
+    /// The index after the last row is placed at the end of the last chunk.
+    fn locate(&self, index: usize) -> (usize, usize) {
+        let chunk = self
+            .starts
+            .partition_point(|&start| start <= index)
+            .saturating_sub(1);
+        (chunk, index - self.starts.get(chunk).copied().unwrap_or(0))
+    }
+
+    fn update_starts(&mut self) {
+        self.starts.clear();
+        let mut start = 0;
+        for chunk in &self.chunks {
+            self.starts.push(start);
+            start += chunk.len();
+        }
+    }
+
+    /// Reads a line of the source file, replacing what cannot be decoded.
+    fn read(&self, span: &Range<u64>) -> Row {
+        Row::from(self.encoding.decode(&self.read_bytes(span)).as_str())
+    }
+
+    fn read_bytes(&self, span: &Range<u64>) -> Vec<u8> {
+        let Some(source) = &self.source else {
+            return Vec::new();
+        };
+        let len = usize::try_from(span.end - span.start).unwrap_or_default();
+        let mut bytes = vec![0; len];
+        // The file is kept open, so this only fails if it is truncated behind our back.
+        // The line is then shown empty rather than stopping the editor.
+        if source.read_exact_at(&mut bytes, span.start).is_err() {
+            bytes.clear();
+        }
+        bytes
+    }
+}
+
+impl FromIterator<Row> for Rope {
+    fn from_iter<I: IntoIterator<Item = Row>>(rows: I) -> Self {
+        let mut rope = Self::default();
+        let mut rows = rows.into_iter().peekable();
+        while rows.peek().is_some() {
+            let chunk: Vec<Line> = rows
+                .by_ref()
+                .take(CHUNK_SIZE)
+                .map(|row| Line::Loaded(Box::new(row)))
+                .collect();
+            rope.len += chunk.len();
+            rope.chunks.push(chunk);
+        }
+        rope.update_starts();
+        rope
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(rope: &Rope) -> Vec<String> {
+        (0..rope.len())
+            .map(|i| rope.get(i).unwrap().as_str().to_string())
+            .collect()
+    }
+
+    fn check(rope: &Rope, model: &[String]) {
+        assert_eq!(rope.len(), model.len());
+        assert_eq!(contents(rope), model);
+        let mut start = 0;
+        for (chunk, &chunk_start) in rope.chunks.iter().zip(&rope.starts) {
+            assert!(!chunk.is_empty() && chunk.len() <= CHUNK_SIZE);
+            assert_eq!(chunk_start, start);
+            start += chunk.len();
+        }
+        assert_eq!(start, model.len());
+    }
+
+    #[test]
+    fn edits_match_a_vec() {
+        let mut model: Vec<String> = (0..CHUNK_SIZE * 3).map(|i| i.to_string()).collect();
+        let mut rope: Rope = model.iter().map(|row| Row::from(row.as_str())).collect();
+        check(&rope, &model);
+
+        // A fixed sequence of pseudo-random edits, small and large enough to
+        // split chunks and to empty them.
+        let mut seed = 7_usize;
+        let mut next = |bound: usize| {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+            (seed >> 33) % bound.max(1)
+        };
+        for step in 0..200 {
+            let index = next(model.len() + 1);
+            match next(4) {
+                0 => {
+                    let rows: Vec<String> = (0..next(CHUNK_SIZE + 10))
+                        .map(|i| format!("{step}.{i}"))
+                        .collect();
+                    rope.splice(
+                        index,
+                        rows.iter().map(|row| Row::from(row.as_str())).collect(),
+                    );
+                    model.splice(index..index, rows);
+                }
+                1 => {
+                    let end = (index + next(CHUNK_SIZE * 2)).min(model.len());
+                    rope.drain(index..end);
+                    model.drain(index..end);
+                }
+                2 if index < model.len() => {
+                    assert_eq!(rope.remove(index).as_str(), model.remove(index));
+                }
+                _ => {
+                    let row = format!("{step}");
+                    rope.insert(index, Row::from(row.as_str()));
+                    model.insert(index, row);
+                }
+            }
+            check(&rope, &model);
+        }
+        while let Some(row) = rope.pop() {
+            assert_eq!(row.as_str(), model.pop().unwrap());
+        }
+        check(&rope, &model);
+        rope.push(Row::from("last"));
+        check(&rope, &[String::from("last")]);
+    }
+
+    #[test]
+    fn drain_past_the_end_or_empty_does_nothing() {
+        let mut rope: Rope = ["a", "b"].into_iter().map(Row::from).collect();
+        rope.drain(1..1);
+        rope.drain(2..5);
+        assert_eq!(contents(&rope), ["a", "b"]);
+        rope.drain(1..5);
+        assert_eq!(contents(&rope), ["a"]);
+    }
+
+    #[test]
+    fn unloaded_lines_are_read_from_the_file() {
+        let path = std::env::temp_dir().join(format!("hecto-rope-{}", std::process::id()));
+        std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        let mut rope = Rope::lazy(File::open(&path).unwrap(), Encoding::Utf8);
+        rope.extend_unloaded(vec![0..3, 4..7, 8..13]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(rope.unchanged_bytes(1).as_deref() == Some(&b"two"[..]));
+        assert_eq!(rope.remove(1).as_str(), "two");
+        rope.insert(0, Row::from("zero"));
+        assert_eq!(contents(&rope), ["zero", "one", "three"]);
+        assert!(rope.unchanged_bytes(0).is_none());
+        assert!(rope.unchanged_bytes(2).as_deref() == Some(&b"three"[..]));
+    }
+}
@@ -1,115 +1,628 @@
-use std::cmp;
+use std::{cmp, ops::Range};
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
-use crate::SearchDirection;
+use crate::{
+    filetype::HighlightingOptions,
+    highlighting::{self, Overlay},
+    search::SearchQuery,
+    SearchDirection,
+};
 
-#[derive(Default)]
+/// A run of graphemes rendered in the same style.
+pub struct Span {
+    pub hl_type: highlighting::Type,
+    pub overlay: Option<Overlay>,
+    pub text: String,
+}
+
+#[derive(Default, Clone)]
 pub struct Row {
     string: String,
-    /// The length of the string in graphemes.
-    len: usize,
+    /// The byte index where each grapheme starts, or `None` when every grapheme is
+    /// a single byte, as in ASCII text.
+    boundaries: Option<Vec<usize>>,
+    /// The highlighting type of each grapheme.
+    highlighting: Vec<highlighting::Type>,
+    is_highlighted: bool,
+    /// The index of the block (e.g. a block comment) open at the start of the row.
+    start_block: Option<usize>,
+    /// The index of the block still open at the end of the row.
+    end_block: Option<usize>,
 }
 
 impl Row {
     pub fn render(&self, start: usize, end: usize) -> String {
-        let end = cmp::min(end, self.string.len());
+        self.slice(start, end).to_string()
+    }
+
+    /// Returns the text of the graphemes from `start` up to `end`.
+    fn slice(&self, start: usize, end: usize) -> &str {
+        let end = cmp::min(end, self.len());
         let start = cmp::min(start, end);
-        self.string
+        &self.string[self.byte_index(start)..self.byte_index(end)]
+    }
+
+    /// Splits the graphemes into runs of the same style, keeping the part visible between
+    /// the screen columns when the graphemes are drawn from column 0.
+    /// `overlays` are grapheme ranges drawn over the syntax highlighting.
+    /// Tabs are expanded to spaces up to the next tab stop.
+    pub fn render_spans(
+        &self,
+        graphemes: Range<usize>,
+        columns: Range<usize>,
+        tab_stop: usize,
+        overlays: &[(Range<usize>, Overlay)],
+    ) -> Vec<Span> {
+        let Range { start, end } = columns;
+        let mut spans: Vec<Span> = Vec::new();
+        let mut column = 0;
+        let visible = self
+            .slice(graphemes.start, graphemes.end)
             .graphemes(true)
-            .skip(start)
-            .take(end - start)
-            .collect()
+            .zip(graphemes.start..);
+        for (grapheme, i) in visible {
+            if column >= end {
+                break;
+            }
+            let next = column + grapheme_width(grapheme, column, tab_stop);
+            if next <= start {
+                column = next;
+                continue;
+            }
+            // A wide character cut by an edge of the screen is drawn as spaces.
+            let text = if grapheme == "\t" || column < start || next > end {
+                " ".repeat(cmp::min(next, end) - cmp::max(column, start))
+            } else {
+                grapheme.to_string()
+            };
+            column = next;
+            let hl_type = self
+                .highlighting
+                .get(i)
+                .copied()
+                .unwrap_or(highlighting::Type::None);
+            let overlay = overlays
+                .iter()
+                .rev()
+                .find(|(range, _)| range.contains(&i))
+                .map(|(_, overlay)| *overlay);
+            match spans.last_mut() {
+                Some(span) if span.hl_type == hl_type && span.overlay == overlay => {
+                    span.text.push_str(&text);
+                }
+                _ => spans.push(Span {
+                    hl_type,
+                    overlay,
+                    text,
+                }),
+            }
+        }
+        spans
     }
 
-    pub fn len(&self) -> usize {
-        self.len
+    /// Converts a grapheme index into the screen column where the grapheme is drawn,
+    /// when the graphemes from `start` are drawn from column 0.
+    pub fn column(&self, start: usize, index: usize, tab_stop: usize) -> usize {
+        self.slice(start, index)
+            .graphemes(true)
+            .fold(0, |column, grapheme| {
+                column + grapheme_width(grapheme, column, tab_stop)
+            })
     }
 
-    pub fn insert(&mut self, at: usize, c: char) {
-        if at >= self.len() {
-            self.string.push(c);
-            self.len += 1;
-            return;
-        }
-        let mut result = String::new();
-        for (i, grapheme) in self.string.graphemes(true).enumerate() {
-            if i == at {
-                result.push(c);
+    /// Converts a screen column into the index of the grapheme drawn there,
+    /// when the graphemes from `start` are drawn from column 0.
+    /// Returns the length of the row if the column is past its end.
+    pub fn index(&self, start: usize, column: usize, tab_stop: usize) -> usize {
+        let mut next = 0;
+        let graphemes = self.slice(start, self.len()).graphemes(true);
+        for (grapheme, i) in graphemes.zip(start..) {
+            next += grapheme_width(grapheme, next, tab_stop);
+            if next > column {
+                return i;
             }
-            result.push_str(grapheme);
         }
-        self.len += 1;
-        self.string = result;
+        self.len()
     }
 
-    pub fn delete(&mut self, at: usize) {
-        if at > self.len() {
-            return;
-        }
-        let mut result = String::new();
+    /// Splits the row into lines fitting the width, breaking after whitespace where possible,
+    /// and returns the index of the grapheme starting each line.
+    /// A row filling its last line is followed by an empty line to hold the cursor at its end.
+    pub fn wrap(&self, width: usize, tab_stop: usize) -> Vec<usize> {
+        let mut starts = vec![0];
+        let mut column = 0;
+        // The index after the last whitespace, where the line can be broken.
+        let mut break_at = None;
         for (i, grapheme) in self.string.graphemes(true).enumerate() {
-            if i != at {
-                result.push_str(grapheme);
+            loop {
+                let line_start = starts.last().copied().unwrap_or_default();
+                if i == line_start || column + grapheme_width(grapheme, column, tab_stop) <= width {
+                    break;
+                }
+                let start = break_at.filter(|&at| at > line_start).unwrap_or(i);
+                starts.push(start);
+                column = self.column(start, i, tab_stop);
+                break_at = None;
+            }
+            column += grapheme_width(grapheme, column, tab_stop);
+            if grapheme.chars().all(char::is_whitespace) {
+                break_at = Some(i + 1);
             }
         }
-        self.len -= 1;
-        self.string = result;
+        if column >= width {
+            starts.push(self.len());
+        }
+        starts
+    }
+
+    /// The length of the row in graphemes.
+    pub fn len(&self) -> usize {
+        self.boundaries.as_ref().map_or(self.string.len(), Vec::len)
+    }
+
+    /// Inserts the text at the byte index, which must be on a character boundary.
+    pub fn insert_str(&mut self, byte_index: usize, s: &str) {
+        let byte_index = cmp::min(byte_index, self.string.len());
+        self.edit(self.grapheme_index(byte_index), |string| {
+            string.insert_str(byte_index, s);
+        });
+    }
+
+    /// Removes the bytes in the range, which must start and end on character boundaries.
+    pub fn remove_str(&mut self, range: Range<usize>) {
+        let end = cmp::min(range.end, self.string.len());
+        let start = cmp::min(range.start, end);
+        self.edit(self.grapheme_index(start), |string| {
+            string.replace_range(start..end, "");
+        });
     }
 
     pub fn append(&mut self, new: &Self) {
-        self.string = format!("{}{}", self.string, new.string);
-        self.len += new.len;
+        self.edit(self.len(), |string| string.push_str(&new.string));
     }
 
     pub fn cut(&mut self, at: usize) -> Self {
-        let mut row = String::new();
-        let mut splitted = String::new();
-        for (i, grapheme) in self.string.graphemes(true).enumerate() {
-            if i < at {
-                row.push_str(grapheme);
-            } else {
-                splitted.push_str(grapheme);
-            }
+        self.split_off(self.byte_index(at))
+    }
+
+    /// Splits the row at the byte index, which must be on a character boundary,
+    /// and returns the rest of it.
+    pub fn split_off(&mut self, byte_index: usize) -> Self {
+        let byte_index = cmp::min(byte_index, self.string.len());
+        let splitted = self.string.split_off(byte_index);
+        if self.string.is_ascii() {
+            self.boundaries = None;
+        } else if let Some(boundaries) = &mut self.boundaries {
+            // A grapheme cut in the middle keeps its start as a shorter grapheme.
+            let kept = boundaries.partition_point(|&start| start < byte_index);
+            boundaries.truncate(kept);
         }
-        self.len = at;
-        self.string = row;
+        self.is_highlighted = false;
         Self::from(splitted.as_str())
     }
 
-    pub fn find(&self, query: &str, at: usize, direction: SearchDirection) -> Option<usize> {
-        if at > self.len {
+    /// Changes the text from the grapheme at `at` onwards and updates the grapheme boundaries.
+    fn edit(&mut self, at: usize, change: impl FnOnce(&mut String)) {
+        // The changed text may be combined with the grapheme before it.
+        let from = at.saturating_sub(1);
+        let start = self.byte_index(from);
+        change(&mut self.string);
+        self.is_highlighted = false;
+        if self.string.is_ascii() {
+            self.boundaries = None;
+            return;
+        }
+        // The graphemes before `from` are unchanged, and ASCII ones start at their index.
+        let mut boundaries = self
+            .boundaries
+            .take()
+            .unwrap_or_else(|| (0..from).collect());
+        boundaries.truncate(from);
+        let rest = self.string[start..].grapheme_indices(true);
+        boundaries.extend(rest.map(|(i, _)| start + i));
+        self.boundaries = Some(boundaries);
+    }
+
+    pub fn find(
+        &self,
+        query: &SearchQuery,
+        at: usize,
+        direction: SearchDirection,
+    ) -> Option<Range<usize>> {
+        if at > self.len() {
             return None;
         }
+        let byte_index = self.byte_index(at);
         let found = match direction {
-            SearchDirection::Forward => {
-                let substring: String = self.string.graphemes(true).skip(at).collect();
-                substring.find(query).map(|i| (substring, i, at))
+            SearchDirection::Forward => query.find_at(&self.string, byte_index),
+            SearchDirection::Backward => query.rfind_before(&self.string, byte_index),
+        };
+        found.map(|range| self.grapheme_index(range.start)..self.grapheme_index(range.end))
+    }
+
+    /// Returns the grapheme ranges of all non-empty matches in the row.
+    pub fn find_all(&self, query: &SearchQuery) -> Vec<Range<usize>> {
+        let matches = query.find_all(&self.string);
+        if matches.is_empty() {
+            return matches;
+        }
+        matches
+            .into_iter()
+            .map(|range| self.grapheme_index(range.start)..self.grapheme_index(range.end))
+            .collect()
+    }
+
+    /// Converts a grapheme index into the byte index where the grapheme starts.
+    pub fn byte_index(&self, grapheme_index: usize) -> usize {
+        match &self.boundaries {
+            Some(boundaries) => boundaries
+                .get(grapheme_index)
+                .copied()
+                .unwrap_or(self.string.len()),
+            None => cmp::min(grapheme_index, self.string.len()),
+        }
+    }
+
+    /// Converts a byte index into the index of the grapheme containing it.
+    fn grapheme_index(&self, byte_index: usize) -> usize {
+        if byte_index >= self.string.len() {
+            return self.len();
+        }
+        match &self.boundaries {
+            Some(boundaries) => boundaries
+                .partition_point(|&start| start <= byte_index)
+                .saturating_sub(1),
+            None => byte_index,
+        }
+    }
+
+    /// Converts the byte index just after some text into the index of the grapheme after it.
+    /// A grapheme the text ends inside of, as when a combining mark follows it, counts as
+    /// part of the text.
+    pub fn grapheme_end(&self, byte_index: usize) -> usize {
+        if byte_index == 0 {
+            0
+        } else {
+            self.grapheme_index(byte_index - 1) + 1
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.string
+    }
+
+    /// Highlights the row, starting inside the given block,
+    /// and returns the block left open at the end of the row.
+    pub fn highlight(
+        &mut self,
+        opts: &HighlightingOptions,
+        start_block: Option<usize>,
+    ) -> Option<usize> {
+        if self.is_highlighted && self.start_block == start_block {
+            return self.end_block;
+        }
+        let graphemes: Vec<(usize, &str)> = self.string.grapheme_indices(true).collect();
+        let mut highlighting = Vec::with_capacity(graphemes.len());
+        let mut open_block = start_block;
+
+        let first_char = self.string.trim_start().chars().next();
+        let line_type = opts
+            .line_prefixes()
+            .iter()
+            .find(|(prefix, _)| start_block.is_none() && first_char == Some(*prefix))
+            .map(|(_, hl_type)| *hl_type);
+        if let Some(hl_type) = line_type {
+            highlighting.resize(graphemes.len(), hl_type);
+        }
+
+        let mut i = highlighting.len();
+        while i < graphemes.len() {
+            let rest = &self.string[graphemes[i].0..];
+            if let Some(block) = open_block.and_then(|index| opts.blocks().get(index)) {
+                let mut len = 1;
+                if rest.starts_with(block.end) {
+                    len = block.end.graphemes(true).count();
+                    open_block = None;
+                }
+                i = highlight_run(&mut highlighting, i, len, block.hl_type);
+                continue;
             }
-            SearchDirection::Backward => {
-                let substring: String = self.string.graphemes(true).take(at).collect();
-                substring.rfind(query).map(|i| (substring, i, 0))
+            if opts
+                .comment()
+                .is_some_and(|comment| rest.starts_with(comment))
+            {
+                let len = graphemes.len() - i;
+                i = highlight_run(&mut highlighting, i, len, highlighting::Type::Comment);
+                continue;
             }
-        };
-        if let Some((substring, match_index, grapheme_index_offset)) = found {
-            let grapheme_indices = substring.grapheme_indices(true).enumerate();
-            for (grapheme_index, (byte_index, _)) in grapheme_indices {
-                if match_index == byte_index {
-                    return Some(grapheme_index_offset + grapheme_index);
+            let block = opts
+                .blocks()
+                .iter()
+                .enumerate()
+                .find(|(_, block)| rest.starts_with(block.start));
+            if let Some((index, block)) = block {
+                let len = block.start.graphemes(true).count();
+                i = highlight_run(&mut highlighting, i, len, block.hl_type);
+                open_block = Some(index);
+                continue;
+            }
+
+            let c = rest.chars().next().unwrap_or_default();
+            if opts.string_quotes().contains(&c) {
+                let len = closing_quote(&graphemes, i, c) + 1 - i;
+                i = highlight_run(&mut highlighting, i, len, highlighting::Type::String);
+                continue;
+            }
+            if opts.characters() && c == '\'' {
+                if let Some(end) = char_literal_end(&graphemes, i) {
+                    let len = end + 1 - i;
+                    i = highlight_run(&mut highlighting, i, len, highlighting::Type::Character);
+                    continue;
                 }
             }
+
+            let prev_is_separator = i == 0 || is_separator(graphemes[i - 1].1);
+            if prev_is_separator && !is_separator(graphemes[i].1) {
+                let len = graphemes[i..]
+                    .iter()
+                    .take_while(|(_, g)| !is_separator(g) || (c.is_ascii_digit() && *g == "."))
+                    .count();
+                let end = graphemes
+                    .get(i + len)
+                    .map_or(self.string.len(), |(byte_index, _)| *byte_index);
+                let word = &self.string[graphemes[i].0..end];
+                let hl_type = if c.is_ascii_digit() {
+                    if opts.numbers() {
+                        highlighting::Type::Number
+                    } else {
+                        highlighting::Type::None
+                    }
+                } else if opts.keywords().contains(&word) {
+                    highlighting::Type::Keyword
+                } else if opts.types().contains(&word) {
+                    highlighting::Type::Builtin
+                } else {
+                    highlighting::Type::None
+                };
+                i = highlight_run(&mut highlighting, i, len, hl_type);
+                continue;
+            }
+            i = highlight_run(&mut highlighting, i, 1, highlighting::Type::None);
         }
-        None
+
+        self.highlighting = highlighting;
+        self.is_highlighted = true;
+        self.start_block = start_block;
+        self.end_block = open_block;
+        open_block
     }
+}
 
-    pub fn as_bytes(&self) -> &[u8] {
-        self.string.as_bytes()
+/// The number of screen columns the grapheme takes when drawn at the column.
+fn grapheme_width(grapheme: &str, column: usize, tab_stop: usize) -> usize {
+    if grapheme == "\t" {
+        tab_stop - column % tab_stop
+    } else {
+        grapheme.width()
+    }
+}
+
+fn highlight_run(
+    highlighting: &mut Vec<highlighting::Type>,
+    at: usize,
+    len: usize,
+    hl_type: highlighting::Type,
+) -> usize {
+    highlighting.resize(at + len, hl_type);
+    at + len
+}
+
+fn is_separator(grapheme: &str) -> bool {
+    grapheme
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_whitespace() || (c.is_ascii_punctuation() && c != '_'))
+}
+
+/// Returns the index of the quote closing the string started at `start`,
+/// or the last index if the string is not closed within the row.
+fn closing_quote(graphemes: &[(usize, &str)], start: usize, quote: char) -> usize {
+    let mut i = start + 1;
+    while i < graphemes.len() {
+        let grapheme = graphemes[i].1;
+        if grapheme == "\\" {
+            i += 2;
+            continue;
+        }
+        if grapheme.starts_with(quote) {
+            return i;
+        }
+        i += 1;
+    }
+    graphemes.len() - 1
+}
+
+/// Returns the end index of a character literal like `'a'` or `'\n'` started at `start`.
+/// A quote not forming a literal (e.g. a lifetime) returns `None`.
+fn char_literal_end(graphemes: &[(usize, &str)], start: usize) -> Option<usize> {
+    let is_quote = |i: usize| graphemes.get(i).is_some_and(|(_, g)| *g == "'");
+    if graphemes.get(start + 1).is_some_and(|(_, g)| *g == "\\") {
+        (start + 3..graphemes.len().min(start + 12)).find(|&i| is_quote(i))
+    } else if is_quote(start + 2) {
+        Some(start + 2)
+    } else {
+        None
     }
 }
 
 impl From<&str> for Row {
     fn from(slice: &str) -> Self {
-        let string = String::from(slice);
-        let len = string.graphemes(true).count();
-        Self { string, len }
+        let mut row = Self::default();
+        row.edit(0, |string| string.push_str(slice));
+        row
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filetype::FileType;
+
+    /// Highlights the rows one after the other, carrying open blocks over, and returns
+    /// the highlighting of each grapheme as a letter.
+    fn highlight(file_name: &str, rows: &[&str]) -> Vec<String> {
+        let file_type = FileType::from(file_name);
+        let mut open_block = None;
+        rows.iter()
+            .map(|text| {
+                let mut row = Row::from(*text);
+                open_block = row.highlight(file_type.highlighting_options(), open_block);
+                letters(&row)
+            })
+            .collect()
+    }
+
+    fn letters(row: &Row) -> String {
+        row.highlighting
+            .iter()
+            .map(|hl_type| match hl_type {
+                highlighting::Type::None => '.',
+                highlighting::Type::Number => 'n',
+                highlighting::Type::String => 's',
+                highlighting::Type::Character => 'c',
+                highlighting::Type::Comment => '/',
+                highlighting::Type::Keyword => 'k',
+                highlighting::Type::Builtin => 'b',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn highlight_rust() {
+        assert_eq!(
+            highlight("a.rs", &["let x: u8 = 1.5; // done"]),
+            ["kkk....bb...nnn..///////"]
+        );
+        assert_eq!(
+            highlight("a.rs", &[r#"f("a\"b", 'c', '\n')"#]),
+            ["..ssssss..ccc..cccc."]
+        );
+    }
+
+    #[test]
+    fn highlight_tells_lifetimes_from_char_literals() {
+        assert_eq!(highlight("a.rs", &["&'a str"]), ["....bbb"]);
+        assert_eq!(highlight("a.rs", &["<'a, 'b'>"]), [".....ccc."]);
+    }
+
+    #[test]
+    fn highlight_carries_block_comments_over_rows() {
+        assert_eq!(
+            highlight("a.c", &["int x; /* one", "two", "*/ return"]),
+            ["bbb....//////", "///", "//.kkkkkk"]
+        );
+    }
+
+    #[test]
+    fn highlight_python_triple_quoted_strings() {
+        assert_eq!(
+            highlight("a.py", &["x = '''it's", "\"\"\" still", "''' + \"s\""]),
+            ["....sssssss", "sssssssss", "sss...sss"]
+        );
+    }
+
+    #[test]
+    fn highlight_line_prefixes() {
+        assert_eq!(
+            highlight("a.md", &["# Title", "  ## Sub", "text `code`"]),
+            ["kkkkkkk", "kkkkkkkk", ".....ssssss"]
+        );
+        // A prefix inside a block is part of the block.
+        assert_eq!(
+            highlight("a.md", &["```", "# not a heading", "```"]),
+            ["sss", "sssssssssssssss", "sss"]
+        );
+        assert_eq!(
+            highlight("a.toml", &["[table]", "key = true # yes"]),
+            ["bbbbbbb", "......kkkk./////"]
+        );
+    }
+
+    #[test]
+    fn highlight_is_redone_after_an_edit_or_another_start_block() {
+        let file_type = FileType::from("a.rs");
+        let opts = file_type.highlighting_options();
+        let mut row = Row::from("x */ fn");
+        assert_eq!(row.highlight(opts, None), None);
+        assert_eq!(letters(&row), ".....kk");
+        assert_eq!(row.highlight(opts, Some(0)), None);
+        assert_eq!(letters(&row), "////.kk");
+
+        row.insert_str(0, "/* ");
+        assert_eq!(row.highlight(opts, Some(0)), None);
+        assert_eq!(letters(&row), "///////.kk");
+        row.remove_str(5..row.as_str().len());
+        assert_eq!(row.highlight(opts, None), Some(0));
+        assert_eq!(letters(&row), "/////");
+    }
+
+    #[test]
+    fn wrap_breaks_after_whitespace() {
+        assert_eq!(Row::from("aaaa bbbb cccc").wrap(10, 8), [0, 10]);
+        assert_eq!(Row::from("aaa bbb ccc").wrap(6, 8), [0, 4, 8]);
+        // A word longer than the line is broken anywhere.
+        assert_eq!(Row::from("abcdefghijkl").wrap(5, 8), [0, 5, 10]);
+        assert_eq!(Row::from("a bcdefgh").wrap(4, 8), [0, 2, 6]);
+    }
+
+    #[test]
+    fn wrap_counts_wide_characters_and_tabs() {
+        assert_eq!(Row::from("日本語日本").wrap(5, 8), [0, 2, 4]);
+        assert_eq!(Row::from("e\u{301}e\u{301}e\u{301}").wrap(2, 8), [0, 2]);
+        assert_eq!(Row::from("abc\td").wrap(6, 4), [0]);
+        // A tab moved to the next line is expanded from its start.
+        assert_eq!(Row::from("abcd\tx").wrap(6, 4), [0, 4]);
+        assert_eq!(Row::from("\tab").wrap(5, 4), [0, 1]);
+    }
+
+    #[test]
+    fn wrap_adds_a_line_for_the_cursor_after_a_full_last_line() {
+        assert_eq!(Row::from("").wrap(5, 8), [0]);
+        assert_eq!(Row::from("abcd").wrap(5, 8), [0]);
+        assert_eq!(Row::from("abcde").wrap(5, 8), [0, 5]);
+        assert_eq!(Row::from("abcdefghij").wrap(5, 8), [0, 5, 10]);
+        assert_eq!(Row::from("日本語").wrap(4, 8), [0, 2]);
+        assert_eq!(Row::from("日本語").wrap(2, 8), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn columns_of_tabs_and_wide_characters() {
+        let row = Row::from("a\t日😀e\u{301}b");
+        let columns: Vec<usize> = (0..=row.len()).map(|i| row.column(0, i, 4)).collect();
+        assert_eq!(columns, [0, 1, 4, 6, 8, 9, 10]);
+        // Drawn from another grapheme, tabs stop at other columns.
+        assert_eq!(row.column(1, 2, 4), 4);
+        assert_eq!(row.column(2, 6, 4), 6);
+        assert_eq!(Row::from("日\tb").column(0, 2, 4), 4);
+    }
+
+    #[test]
+    fn index_of_a_column_inside_a_tab_or_wide_character() {
+        let row = Row::from("a\t日😀e\u{301}b");
+        let indices: Vec<usize> = (0..12).map(|column| row.index(0, column, 4)).collect();
+        assert_eq!(indices, [0, 1, 1, 1, 2, 2, 3, 3, 4, 5, 6, 6]);
+        assert_eq!(row.index(2, 3, 4), 3);
+        assert_eq!(row.index(2, 100, 4), row.len());
+    }
+
+    #[test]
+    fn column_and_index_round_trip() {
+        let row = Row::from("\tx 日本語\t😀\u{1f1eb}\u{1f1f7} e\u{301}\tend");
+        for start in 0..row.len() {
+            for i in start..=row.len() {
+                assert_eq!(row.index(start, row.column(start, i, 8), 8), i);
+            }
+        }
     }
 }
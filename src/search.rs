@@ -0,0 +1,227 @@
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+
+#[derive(Default, PartialEq, Clone, Copy)]
+pub enum CaseSensitivity {
+    #[default]
+    Sensitive,
+    Insensitive,
+    /// Insensitive unless the query contains an uppercase letter.
+    Smart,
+}
+
+impl CaseSensitivity {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Sensitive => Self::Insensitive,
+            Self::Insensitive => Self::Smart,
+            Self::Smart => Self::Sensitive,
+        }
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum SearchDirection {
+    Forward,
+    Backward,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct SearchOptions {
+    /// Treat the query as a regular expression instead of a literal string.
+    pub regex: bool,
+    pub case: CaseSensitivity,
+    /// Only match the query at word boundaries.
+    pub whole_word: bool,
+}
+
+impl SearchOptions {
+    /// A short description of the active options, shown in the search prompt.
+    pub fn label(self) -> String {
+        let mut label = String::new();
+        if self.regex {
+            label.push_str("[regex] ");
+        }
+        match self.case {
+            CaseSensitivity::Sensitive => {}
+            CaseSensitivity::Insensitive => label.push_str("[ignore-case] "),
+            CaseSensitivity::Smart => label.push_str("[smart-case] "),
+        }
+        if self.whole_word {
+            label.push_str("[word] ");
+        }
+        label
+    }
+}
+
+/// A compiled search query. Literal queries are escaped into a regex
+/// so that every mode shares the same matching logic.
+#[derive(Clone)]
+pub struct SearchQuery {
+    regex: Regex,
+    is_empty: bool,
+    /// Whether `$1`-style references in a replacement refer to capture groups.
+    expand: bool,
+}
+
+impl SearchQuery {
+    pub fn new(query: &str, options: SearchOptions) -> Result<Self, regex::Error> {
+        let mut pattern = if options.regex {
+            query.to_string()
+        } else {
+            regex::escape(query)
+        };
+        if options.whole_word {
+            pattern = format!(r"\b(?:{pattern})\b");
+        }
+        let case_insensitive = match options.case {
+            CaseSensitivity::Sensitive => false,
+            CaseSensitivity::Insensitive => true,
+            CaseSensitivity::Smart => !has_uppercase(query, options.regex),
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(case_insensitive)
+            .build()?;
+        Ok(Self {
+            regex,
+            is_empty: query.is_empty(),
+            expand: options.regex,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.is_empty
+    }
+
+    /// Finds the first match starting at or after the byte index `start`.
+    pub fn find_at(&self, text: &str, start: usize) -> Option<Range<usize>> {
+        self.regex.find_at(text, start).map(|m| m.range())
+    }
+
+    /// Builds the text replacing the match starting at the byte index `start` of the text.
+    /// The match is taken in the whole text so that lookaround like `\b` sees its context.
+    /// Returns `None` if there is no match there.
+    pub fn replacement(&self, text: &str, start: usize, replacement: &str) -> Option<String> {
+        let captures = self
+            .regex
+            .captures_at(text, start)
+            .filter(|captures| captures.get(0).is_some_and(|m| m.start() == start))?;
+        if !self.expand {
+            return Some(replacement.to_string());
+        }
+        let mut expanded = String::new();
+        captures.expand(replacement, &mut expanded);
+        Some(expanded)
+    }
+
+    /// Finds all non-overlapping, non-empty matches.
+    pub fn find_all(&self, text: &str) -> Vec<Range<usize>> {
+        self.regex
+            .find_iter(text)
+            .map(|m| m.range())
+            .filter(|range| !range.is_empty())
+            .collect()
+    }
+
+    /// Finds the last match starting before the byte index `end`, which may overlap
+    /// the match before it, as the one a forward search would find from its start.
+    pub fn rfind_before(&self, text: &str, end: usize) -> Option<Range<usize>> {
+        let mut last = None;
+        let mut start = 0;
+        while let Some(m) = self.regex.find_at(text, start) {
+            if m.start() >= end {
+                break;
+            }
+            last = Some(m.range());
+            match text[m.start()..].chars().next() {
+                Some(c) => start = m.start() + c.len_utf8(),
+                None => break,
+            }
+        }
+        last
+    }
+}
+
+/// Whether the query has an uppercase letter. In a regex, escapes like `\S` or `\p{Lu}`
+/// are not text to match, so their letters do not count.
+fn has_uppercase(query: &str, regex: bool) -> bool {
+    let mut chars = query.chars();
+    while let Some(c) = chars.next() {
+        if regex && c == '\\' {
+            // A Unicode class names its category after the escaped letter.
+            if let Some('p' | 'P') = chars.next() {
+                if chars.next() == Some('{') {
+                    chars.by_ref().find(|&c| c == '}');
+                }
+            }
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regex(query: &str) -> SearchQuery {
+        let options = SearchOptions {
+            regex: true,
+            ..SearchOptions::default()
+        };
+        SearchQuery::new(query, options).unwrap()
+    }
+
+    #[test]
+    fn replacement_sees_the_text_around_the_match() {
+        let query = regex(r"\Bbar");
+        let text = "foobar";
+        let start = query.find_at(text, 0).unwrap().start;
+        assert_eq!(
+            query.replacement(text, start, "baz").as_deref(),
+            Some("baz")
+        );
+    }
+
+    #[test]
+    fn replacement_expands_capture_groups() {
+        let query = regex(r"(\w+)=(\w+)");
+        let text = "let a=b;";
+        assert_eq!(query.replacement(text, 4, "$2=$1").as_deref(), Some("b=a"));
+        assert_eq!(query.replacement(text, 3, "$2=$1"), None);
+    }
+
+    #[test]
+    fn literal_replacement_is_not_expanded() {
+        let query = SearchQuery::new("a", SearchOptions::default()).unwrap();
+        assert_eq!(query.replacement("a", 0, "$0").as_deref(), Some("$0"));
+    }
+
+    #[test]
+    fn smart_case_ignores_regex_escapes() {
+        let smart = |regex| SearchOptions {
+            regex,
+            case: CaseSensitivity::Smart,
+            ..SearchOptions::default()
+        };
+        let find =
+            |query, options, text| SearchQuery::new(query, options).unwrap().find_at(text, 0);
+        assert_eq!(find(r"a\Sc", smart(true), "ABC"), Some(0..3));
+        assert_eq!(find(r"\pLb", smart(true), "AB"), Some(0..2));
+        assert_eq!(find(r"\p{Lu}b", smart(true), "AB"), Some(0..2));
+        assert_eq!(find(r"\SB", smart(true), "ab"), None);
+        assert_eq!(find(r"\S", smart(false), "\\s"), None);
+    }
+
+    #[test]
+    fn backward_search_finds_overlapping_matches() {
+        let query = SearchQuery::new("aa", SearchOptions::default()).unwrap();
+        assert_eq!(query.rfind_before("aaa", 2), Some(1..3));
+        assert_eq!(query.rfind_before("aaa", 1), Some(0..2));
+        assert_eq!(query.rfind_before("aaa", 0), None);
+        assert_eq!(query.rfind_before("a\u{e9}aa", 9), Some(3..5));
+        assert_eq!(regex(r"\bab").rfind_before("abab ab", 7), Some(5..7));
+        assert_eq!(regex(r"\bab").rfind_before("abab ab", 5), Some(0..2));
+    }
+}
@@ -1,35 +1,73 @@
 use crate::Position;
-use std::io::{self, Stdout, Write};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use signal_hook::{consts::SIGWINCH, iterator::Signals};
+use std::{
+    io::{self, Stdout, Write},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::Instant,
+};
 use termion::{
     color,
-    event::Key,
-    input::TermRead,
+    event::{self, Key},
+    input::TermReadEventsAndRaw,
     raw::{IntoRawMode, RawTerminal},
+    style,
 };
 
+#[derive(PartialEq)]
+pub enum Event {
+    Key(Key),
+    /// A navigation key pressed with Shift.
+    Shift(Key),
+    /// Text pasted while bracketed paste mode is enabled.
+    Paste(String),
+    /// The terminal window was resized. The size is already updated.
+    Resize,
+}
+
 pub struct Size {
     pub width: u16,
     pub height: u16,
 }
 
 pub struct Terminal {
-    _stdout: RawTerminal<Stdout>,
+    /// Keeps the terminal in raw mode. `None` when there is no terminal, as in tests.
+    _stdout: Option<RawTerminal<Stdout>>,
     size: Size,
+    /// Input and resize events, gathered by background threads.
+    events: Receiver<Result<Event, io::Error>>,
 }
 
 impl Terminal {
     pub fn default() -> Result<Self, io::Error> {
-        let size = termion::terminal_size()?;
         let stdout = io::stdout().into_raw_mode()?;
+        // Ask the terminal to wrap pasted text so that it can be told apart from typing.
+        print!("\x1b[?2004h");
+        let (sender, events) = mpsc::channel();
+        spawn_resize_listener(sender.clone())?;
+        spawn_input_reader(sender);
         Ok(Self {
-            _stdout: stdout,
-            size: Size {
-                width: size.0,
-                height: size.1.saturating_sub(2),
-            },
+            _stdout: Some(stdout),
+            size: current_size()?,
+            events,
         })
     }
 
+    /// A terminal of the given size that receives the events, and then no more input.
+    #[cfg(test)]
+    pub fn headless(size: Size, input: Vec<Event>) -> Self {
+        let (sender, events) = mpsc::channel();
+        for event in input {
+            let _ = sender.send(Ok(event));
+        }
+        Self {
+            _stdout: None,
+            size,
+            events,
+        }
+    }
+
     pub fn flush() -> Result<(), io::Error> {
         io::stdout().flush()
     }
@@ -58,6 +96,20 @@ impl Terminal {
         print!("{}", color::Fg(color::Reset));
     }
 
+    pub fn set_inverted() {
+        print!("{}", style::Invert);
+    }
+
+    pub fn reset_inverted() {
+        print!("{}", style::NoInvert);
+    }
+
+    /// Sets the system clipboard through the terminal with an OSC 52 escape sequence.
+    pub fn set_clipboard(text: &str) -> Result<(), io::Error> {
+        print!("\x1b]52;c;{}\x07", STANDARD.encode(text));
+        Self::flush()
+    }
+
     pub fn cursor_hide() {
         print!("{}", termion::cursor::Hide);
     }
@@ -73,15 +125,174 @@ impl Terminal {
         print!("{}", termion::cursor::Goto(x, y));
     }
 
-    pub fn read_key() -> Result<Key, io::Error> {
-        loop {
-            if let Some(key) = io::stdin().lock().keys().next() {
-                return key;
-            }
+    /// Waits for the next event. A resize updates the size before it is returned.
+    pub fn read_event(&mut self) -> Result<Event, io::Error> {
+        let event = self.events.recv().map_err(|_| input_closed())?;
+        self.receive(event)
+    }
+
+    /// Same as `read_event`, but gives up at the deadline and returns `None`.
+    pub fn read_event_until(&mut self, deadline: Instant) -> Result<Option<Event>, io::Error> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match self.events.recv_timeout(timeout) {
+            Ok(event) => self.receive(event).map(Some),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(input_closed()),
         }
     }
 
+    fn receive(&mut self, event: Result<Event, io::Error>) -> Result<Event, io::Error> {
+        let event = event?;
+        if event == Event::Resize {
+            self.size = current_size()?;
+        }
+        Ok(event)
+    }
+
     pub fn size(&self) -> &Size {
         &self.size
     }
 }
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?2004l");
+        let _ = Self::flush();
+    }
+}
+
+fn input_closed() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "input closed")
+}
+
+/// The size of the area for the document, leaving two rows for the status and message bars.
+fn current_size() -> Result<Size, io::Error> {
+    let (width, height) = termion::terminal_size()?;
+    Ok(Size {
+        width,
+        height: height.saturating_sub(2),
+    })
+}
+
+fn spawn_resize_listener(sender: Sender<Result<Event, io::Error>>) -> Result<(), io::Error> {
+    let mut signals = Signals::new([SIGWINCH])?;
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            if sender.send(Ok(Event::Resize)).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(())
+}
+
+fn spawn_input_reader(sender: Sender<Result<Event, io::Error>>) {
+    thread::spawn(move || {
+        let mut events = io::stdin().events_and_raw();
+        while let Some(event) = read_event(&mut events) {
+            let failed = event.is_err();
+            if sender.send(event).is_err() || failed {
+                break;
+            }
+        }
+    });
+}
+
+/// Reads input events until one the editor handles, combining a bracketed paste into one event.
+fn read_event<I>(events: &mut I) -> Option<Result<Event, io::Error>>
+where
+    I: Iterator<Item = Result<(event::Event, Vec<u8>), io::Error>>,
+{
+    loop {
+        let event = match events.next()? {
+            Ok((event, _)) => event,
+            Err(err) => return Some(Err(err)),
+        };
+        match event {
+            event::Event::Key(key) => return Some(Ok(Event::Key(key))),
+            event::Event::Unsupported(bytes) if bytes == PASTE_START => {
+                return Some(read_paste(events).map(Event::Paste));
+            }
+            // Termion does not parse keys with modifiers such as `ESC [1;2D` (Shift+Left).
+            event::Event::Unsupported(bytes) => {
+                if let Some(key) = parse_shift_key(&bytes) {
+                    return Some(Ok(Event::Shift(key)));
+                }
+            }
+            event::Event::Mouse(_) => {}
+        }
+    }
+}
+
+/// Reads the pasted text up to the end of the bracketed paste.
+/// The text is taken from the bytes read rather than the keys, since Termion turns both
+/// `\r` and `\n` into `\n` and a CRLF line break would become two.
+fn read_paste<I>(events: &mut I) -> Result<String, io::Error>
+where
+    I: Iterator<Item = Result<(event::Event, Vec<u8>), io::Error>>,
+{
+    let mut bytes = Vec::new();
+    for event in events {
+        let (event, raw) = event?;
+        if matches!(event, event::Event::Unsupported(ref end) if end == PASTE_END) {
+            break;
+        }
+        bytes.extend(raw);
+    }
+    let text = String::from_utf8_lossy(&bytes);
+    Ok(text.replace("\r\n", "\n").replace('\r', "\n"))
+}
+
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+fn parse_shift_key(bytes: &[u8]) -> Option<Key> {
+    match bytes {
+        b"\x1b[1;2A" => Some(Key::Up),
+        b"\x1b[1;2B" => Some(Key::Down),
+        b"\x1b[1;2C" => Some(Key::Right),
+        b"\x1b[1;2D" => Some(Key::Left),
+        b"\x1b[1;2H" => Some(Key::Home),
+        b"\x1b[1;2F" => Some(Key::End),
+        b"\x1b[5;2~" => Some(Key::PageUp),
+        b"\x1b[6;2~" => Some(Key::PageDown),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(input: &[u8]) -> Vec<Event> {
+        let mut events = input.events_and_raw();
+        std::iter::from_fn(|| read_event(&mut events))
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn paste_keeps_line_breaks_and_tabs() {
+        let paste = |text: &str| Event::Paste(text.to_string());
+        assert!(events(b"\x1b[200~a\r\nb\x1b[201~") == [paste("a\nb")]);
+        assert!(events(b"\x1b[200~a\rb\n\tc\r\n\x1b[201~") == [paste("a\nb\n\tc\n")]);
+        assert!(
+            events("\x1b[200~caf\u{e9}\x1b[201~x".as_bytes())
+                == [paste("caf\u{e9}"), Event::Key(Key::Char('x'))]
+        );
+    }
+
+    #[test]
+    fn shift_keys_are_parsed() {
+        assert!(
+            events(b"\x1b[1;2D\x1b[6;2~\x1b[D")
+                == [
+                    Event::Shift(Key::Left),
+                    Event::Shift(Key::PageDown),
+                    Event::Key(Key::Left),
+                ]
+        );
+        // Other modifiers are ignored.
+        assert!(events(b"\x1b[1;5Da") == [Event::Key(Key::Char('a'))]);
+    }
+}